@@ -6,12 +6,31 @@ let
   cfg = config.programs.forest;
   tomlFormat = pkgs.formats.toml {};
   forestConfig = {
+    version = 1;
     general = {
       base_dir = cfg.settings.general.baseDir;
       copy = cfg.settings.general.copy;
+      copy_ignored = cfg.settings.general.copyIgnored;
+      templates = cfg.settings.general.templates;
       exec = cfg.settings.general.exec;
+      on_error = cfg.settings.general.onError;
+      hooks = cfg.settings.general.hooks;
+      tree_name_template = cfg.settings.general.treeNameTemplate;
+      tree_name_normalization = cfg.settings.general.treeNameNormalization;
+    } // optionalAttrs (cfg.settings.general.ports != null) {
+      ports = {
+        range = cfg.settings.general.ports.range;
+        per_tree = cfg.settings.general.ports.perTree;
+      };
+    } // optionalAttrs (cfg.settings.general.branchTemplate != null) {
+      branch_template = cfg.settings.general.branchTemplate;
     };
-    roots = cfg.settings.roots;
+    roots = mapAttrs (_: root: {
+      inherit (root) copy templates env hooks exec replace;
+      copy_ignored = root.copyIgnored;
+      "inherit" = root."inherit";
+    }) cfg.settings.roots;
+    profiles = cfg.settings.profiles;
   };
   configFile = tomlFormat.generate "config.toml" forestConfig;
 
@@ -52,36 +71,149 @@ in {
         };
 
         copy = mkOption {
-          type = types.listOf types.str;
+          type = types.listOf (types.either types.str types.attrs);
           default = [ ".env" ".envrc" ];
           description = "Files to copy when creating worktrees";
         };
 
-        exec = mkOption {
+        copyIgnored = mkOption {
           type = types.listOf types.str;
           default = [];
+          description = "Patterns of git-ignored files to copy when creating worktrees";
+        };
+
+        templates = mkOption {
+          type = types.listOf (types.either types.str types.attrs);
+          default = [];
+          description = "Files to render into worktrees when creating them";
+        };
+
+        exec = mkOption {
+          type = types.listOf (types.either types.str types.attrs);
+          default = [];
           description = "Commands to execute when creating worktrees";
         };
+
+        hooks = mkOption {
+          type = types.attrsOf (types.listOf (types.either types.str types.attrs));
+          default = {};
+          description = "Commands to run on pre_create, post_create, pre_delete, post_delete and post_clone";
+        };
+
+        onError = mkOption {
+          type = types.enum [ "abort" "continue" "prompt" ];
+          default = "continue";
+          description = "What to do when a command in exec fails";
+        };
+
+        ports = mkOption {
+          type = types.nullOr (types.submodule {
+            options = {
+              range = mkOption {
+                type = types.listOf types.port;
+                example = [ 4000 4999 ];
+                description = "First and last port (inclusive) forest may hand out";
+              };
+
+              perTree = mkOption {
+                type = types.ints.positive;
+                default = 1;
+                description = "How many ports each worktree gets";
+              };
+            };
+          });
+          default = null;
+          description = "Port range to allocate FOREST_PORT values from, or null to disable";
+        };
+
+        treeNameTemplate = mkOption {
+          type = types.str;
+          default = "{root}--{branch}";
+          description = "Directory of new worktrees inside trees/, with {root} and {branch} replaced";
+        };
+
+        treeNameNormalization = {
+          replacement = mkOption {
+            type = types.str;
+            default = "--";
+            description = "What runs of characters other than letters, digits, - and _ become";
+          };
+
+          lowercase = mkOption {
+            type = types.bool;
+            default = false;
+            description = "Whether branch names are lowercased in directory names";
+          };
+        };
+
+        branchTemplate = mkOption {
+          type = types.nullOr types.str;
+          default = null;
+          example = "{user}/{ticket}-{slug}";
+          description = "Template new branch names are built from, or null to use names as given";
+        };
       };
 
       roots = mkOption {
         type = types.attrsOf (types.submodule {
           options = {
             copy = mkOption {
-              type = types.listOf types.str;
+              type = types.listOf (types.either types.str types.attrs);
               default = [];
               description = "Repository-specific files to copy";
             };
 
-            exec = mkOption {
+            copyIgnored = mkOption {
               type = types.listOf types.str;
               default = [];
+              description = "Repository-specific patterns of git-ignored files to copy";
+            };
+
+            "inherit" = mkOption {
+              type = types.bool;
+              default = true;
+              description = "Whether general settings apply to this repository";
+            };
+
+            replace = mkOption {
+              type = types.listOf (types.enum [ "copy" "copy_ignored" "templates" "exec" "hooks" ]);
+              default = [];
+              description = "Settings that replace the general ones instead of extending them";
+            };
+
+            templates = mkOption {
+              type = types.listOf (types.either types.str types.attrs);
+              default = [];
+              description = "Repository-specific files to render";
+            };
+
+            env = mkOption {
+              type = types.attrsOf types.str;
+              default = {};
+              description = "Repository-specific environment variables for commands";
+            };
+
+            hooks = mkOption {
+              type = types.attrsOf (types.listOf (types.either types.str types.attrs));
+              default = {};
+              description = "Repository-specific lifecycle hooks";
+            };
+
+            exec = mkOption {
+              type = types.listOf (types.either types.str types.attrs);
+              default = [];
               description = "Repository-specific commands to execute";
             };
           };
         });
         default = {};
-        description = "Per-repository configuration overrides";
+        description = "Per-repository configuration, extending the general one";
+      };
+
+      profiles = mkOption {
+        type = types.attrsOf types.attrs;
+        default = {};
+        description = "Named workspaces with their own base_dir, general settings and roots, written as in config.toml";
       };
     };
   };
@@ -1,27 +1,50 @@
-use std::path::PathBuf;
+use anyhow::Context;
+use dialoguer::Confirm;
+use std::path::{Path, PathBuf};
 
 use crate::trees;
 use crate::roots;
-use crate::config::{Config, load_config};
-use crate::utils::path::config_dir;
+use crate::trust;
+use crate::config::{self, CONFIG_FILE, Config, ResolvedConfig, activate_profile, load_config, load_repo_config, resolve_config};
+use crate::utils::path::{config_dir, config_file_override, expand as expand_path};
 use crate::utils::cli_ui;
 
 pub struct Application {
+    /// `base_dir` from the configuration, expanded
+    pub base_dir: PathBuf,
     pub roots_dir: PathBuf,
     pub trees_dir: PathBuf,
+    /// Where forest keeps its own bookkeeping (e.g. port allocations)
+    pub state_dir: PathBuf,
+    /// Where the user's configuration and trust store live
+    pub config_dir: PathBuf,
+    /// Configuration as seen from the active profile
     pub config: Config,
+    pub profile: Option<String>,
 }
 
 impl Application {
-    pub fn new() -> Self {
-        let config_dir = config_dir().unwrap();
-        let config = load_config(config_dir).unwrap();
+    pub fn new(config_flag: Option<PathBuf>, profile_flag: Option<String>) -> anyhow::Result<Self> {
+        let (config_file, create_default) = locate_config(config_flag)?;
+        let config_dir = config_file.parent().map(Path::to_path_buf).unwrap_or_default();
+        let (config, profile) = activate_profile(load_config(&config_file, create_default)?, &config_file, profile_flag)?;
 
-        Self {
-            roots_dir: PathBuf::from(&config.general.base_dir).join("roots"),
-            trees_dir: PathBuf::from(&config.general.base_dir).join("trees"),
+        let base_dir = match std::env::var("FOREST_BASE_DIR") {
+            Ok(value) if !value.is_empty() => expand_path(&value, &std::env::current_dir()?)
+                .with_context(|| format!("Invalid FOREST_BASE_DIR '{}'", value))?,
+            _ => expand_path(&config.general.base_dir, &config_dir)
+                .with_context(|| format!("Invalid base_dir '{}'", config.general.base_dir))?,
+        };
+
+        Ok(Self {
+            roots_dir: base_dir.join("roots"),
+            trees_dir: base_dir.join("trees"),
+            state_dir: base_dir.join(".forest"),
+            base_dir,
+            config_dir,
             config,
-        }
+            profile,
+        })
     }
 
     pub fn setup(&self) {
@@ -29,8 +52,21 @@ impl Application {
         self.handle(std::fs::create_dir_all(&self.trees_dir));
     }
 
+    /// Settings of `root`, including those in the repository's own `forest.toml`
+    /// once the user trusts it. Without trust, only the user's settings apply
+    pub fn resolved_config(&self, root: &str) -> anyhow::Result<ResolvedConfig> {
+        let root_path = self.roots_dir.join(root);
+        let repo_config = match load_repo_config(&root_path)? {
+            Some((repo_config, content)) => trust::check(self, root, &root_path, &repo_config, &content)?
+                .then_some(repo_config),
+            None => None,
+        };
+
+        Ok(resolve_config(&self.config, root, repo_config.as_ref()))
+    }
+
     pub fn roots_clone(&self, repository_address: String) {
-        match roots::clone::call(&self.roots_dir, repository_address) {
+        match roots::clone::call(self, repository_address) {
             Ok(root) => {
                 let msg = format!("{} cloned into {}", root.name, root.path.display());
                 println!("\n{}", cli_ui::success(&msg));
@@ -64,16 +100,25 @@ impl Application {
         }
     }
 
+    pub fn roots_config(&self, root: String) {
+        print!("{}", self.handle(roots::config::call(self, &root)))
+    }
+
     pub fn roots_exec(&self, root: String, command: String) {
-        self.handle(roots::exec::call(&self.roots_dir, root, command))
+        self.handle(roots::exec::call(self, root, command))
     }
 
     pub fn trees_clean(&self, root: Option<String>) {
         self.handle(trees::clean::call(&self, root))
     }
 
-    pub fn trees_create(&self, root: String, new_branch_name: String) {
-        self.handle(trees::create::call(&self, &root, &new_branch_name))
+    pub fn trees_create(&self, root: String, new_branch_name: String, exact: bool) {
+        let branch = self.handle(trees::branch::name(self, &new_branch_name, exact));
+        if branch != new_branch_name {
+            println!("{}", cli_ui::context(&format!("Creating branch '{}'", branch)));
+        }
+
+        self.handle(trees::create::call(&self, &root, &branch))
     }
 
     pub fn trees_delete(&self, root: String, tree: String) {
@@ -85,7 +130,8 @@ impl Application {
             Ok(roots_trees) => {
                 roots_trees.iter().for_each(|(root, trees)| {
                     for tree in trees {
-                        println!("{}", cli_ui::tree_with_path(&root, &tree));
+                        let status = trees::background::status(&tree.path);
+                        println!("{}", cli_ui::tree_with_status(&root, &tree, status));
                     }
                 });
             },
@@ -97,6 +143,10 @@ impl Application {
         self.handle(trees::exec::call(&self, &root, &tree, command))
     }
 
+    pub fn trees_logs(&self, root: String, tree: String, follow: bool) {
+        self.handle(trees::logs::call(self, &root, &tree, follow, &mut std::io::stdout()))
+    }
+
     pub fn trees_path(&self, root: String, tree: String) {
         match trees::get::call(&self, &root, &tree) {
             Ok(t) => {
@@ -106,6 +156,66 @@ impl Application {
         }
     }
 
+    pub fn trees_ports(&self, root: Option<String>) {
+        match trees::ports::call(self, &root) {
+            Ok(allocations) => {
+                if allocations.is_empty() {
+                    println!("\n{}", cli_ui::warn("No ports allocated"));
+                    return;
+                }
+
+                for (tree_name, allocation) in allocations {
+                    println!("{}", cli_ui::tree_ports(&tree_name, &allocation));
+                }
+            },
+            Err(err) => self.expected_error(err)
+        }
+    }
+
+    pub fn trees_setup(&self, root: String, tree: Option<String>, copy_only: bool, exec_only: bool) {
+        let mode = match (copy_only, exec_only) {
+            (true, _) => trees::setup::SetupMode::CopyOnly,
+            (_, true) => trees::setup::SetupMode::ExecOnly,
+            _ => trees::setup::SetupMode::All,
+        };
+
+        let result = match tree {
+            Some(tree) => trees::setup::call(self, &root, &tree, mode),
+            None => trees::setup::call_all(self, &root, mode),
+        };
+
+        match result {
+            Ok(()) => println!("\n{}", cli_ui::success("Setup complete")),
+            Err(err) => self.expected_error(err)
+        }
+    }
+
+    pub fn trust_list(&self) {
+        match trust::list(self) {
+            Ok(trusted) => {
+                if trusted.is_empty() {
+                    println!("\n{}", cli_ui::warn("No trusted repository configurations"));
+                    return;
+                }
+
+                for (root_path, config) in trusted {
+                    println!("{}", cli_ui::trusted_config(&root_path, &config));
+                }
+            },
+            Err(err) => self.expected_error(err)
+        }
+    }
+
+    pub fn trust_revoke(&self, root: String) {
+        match trust::revoke(self, &root) {
+            Ok(root_path) => {
+                let msg = format!("{} is no longer trusted", root_path.display());
+                println!("\n{}", cli_ui::success(&msg));
+            },
+            Err(err) => self.expected_error(err)
+        }
+    }
+
     fn expected_error<T: std::fmt::Display>(&self, message: T) {
         let msg = format!("Error: {}", message);
         eprintln!("\n{}", cli_ui::error(&msg));
@@ -124,6 +234,103 @@ impl Application {
     }
 }
 
+/// The configuration file, and whether forest may write a default one there:
+/// only in its default location, never where `--config`/`FOREST_CONFIG` point
+fn locate_config(config_flag: Option<PathBuf>) -> anyhow::Result<(PathBuf, bool)> {
+    match config_file_override(config_flag) {
+        Some(file) => Ok((std::path::absolute(file)?, false)),
+        None => {
+            let config_dir = config_dir().map_err(|e| anyhow::anyhow!("{}", e))?;
+            Ok((config_dir.join(CONFIG_FILE), true))
+        }
+    }
+}
+
+/// Location of the configuration file. Unlike the commands above, the
+/// `config_*` ones only need this file, so they work even when it is invalid
+pub fn config_file(config_flag: Option<PathBuf>) -> PathBuf {
+    match locate_config(config_flag) {
+        Ok((config_file, _)) => config_file,
+        Err(err) => config_error(err),
+    }
+}
+
+pub fn config_show(config_file: &Path) {
+    match config::commands::show(config_file) {
+        Ok(content) => print!("{}", content),
+        Err(err) => config_error(err),
+    }
+}
+
+pub fn config_show_effective(config_file: &Path, profile_flag: Option<String>) {
+    match config::commands::show_effective(config_file, profile_flag) {
+        Ok(content) => print!("{}", content),
+        Err(err) => config_error(err),
+    }
+}
+
+pub fn config_get(config_file: &Path, profile_flag: Option<String>, key: &str) {
+    match config::commands::get(config_file, profile_flag, key) {
+        Ok(value) => println!("{}", value),
+        Err(err) => config_error(err),
+    }
+}
+
+pub fn config_set(config_file: &Path, key: &str, value: &str) {
+    match config::commands::set(config_file, key, value) {
+        Ok(()) => println!("\n{}", cli_ui::success(&format!("{} updated", key))),
+        Err(err) => config_error(err),
+    }
+}
+
+/// Opens the editor until the configuration is valid or the user gives up
+pub fn config_edit(config_file: &Path) {
+    loop {
+        if let Err(err) = config::commands::edit(config_file) {
+            config_error(err);
+        }
+
+        let err = match config::commands::check_with_warnings(config_file) {
+            Ok((_, warnings)) => return print_config_warnings(&warnings),
+            Err(err) => err,
+        };
+
+        eprintln!("\n{}", cli_ui::error(&format!("Error: {:#}", err)));
+
+        let retry = Confirm::new()
+            .with_prompt(cli_ui::prompt("Edit the configuration again?"))
+            .default(true)
+            .interact()
+            .unwrap_or(false);
+
+        if !retry {
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn config_check(config_file: &Path) {
+    match config::commands::check_with_warnings(config_file) {
+        Ok((_, warnings)) => {
+            print_config_warnings(&warnings);
+            println!("\n{}", cli_ui::success("Configuration is valid"));
+        },
+        Err(err) => config_error(err),
+    }
+}
+
+fn print_config_warnings(warnings: &[String]) {
+    for key in warnings {
+        println!("{}", cli_ui::warn(&format!("Unknown key '{}' is ignored", key)));
+    }
+}
+
+fn config_error<T: std::fmt::Display>(message: T) -> ! {
+    let msg = format!("Error: {:#}", message);
+    eprintln!("\n{}", cli_ui::error(&msg));
+    std::process::exit(1);
+}
+
 #[cfg(test)]
 pub struct TestApplication {
     pub application: Application,
@@ -149,16 +356,23 @@ pub fn test_application(
     let base_dir = base_temp_dir.path();
 
     let application = Application {
+        base_dir: base_dir.to_path_buf(),
         roots_dir: base_dir.join("roots"),
         trees_dir: base_dir.join("trees"),
+        state_dir: base_dir.join(".forest"),
+        config_dir: base_dir.join("config"),
         config: crate::config::Config {
+            version: crate::config::CONFIG_VERSION,
             general: crate::config::GeneralConfig {
                 base_dir: base_dir.to_string_lossy().to_string(),
-                copy,
-                exec,
+                copy: copy.into_iter().map(Into::into).collect(),
+                exec: exec.into_iter().map(Into::into).collect(),
+                ..Default::default()
             },
             roots,
+            profiles: Default::default(),
         },
+        profile: None,
     };
 
     application.setup();
@@ -168,3 +382,22 @@ pub fn test_application(
         _temp_dir: base_temp_dir,
     }
 }
+
+/// Local git repository with a single commit on `main`
+#[cfg(test)]
+pub fn init_test_repo(path: &std::path::Path) {
+    std::fs::create_dir_all(path).unwrap();
+
+    for args in [
+        vec!["init", "-b", "main"],
+        vec!["config", "user.name", "Test User"],
+        vec!["config", "user.email", "test@example.com"],
+        vec!["commit", "--allow-empty", "-m", "initial"],
+    ] {
+        std::process::Command::new("git")
+            .args(args)
+            .current_dir(path)
+            .output()
+            .unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Key, Table, Value};
+
+use super::loader::{migrate, validate};
+use super::{CONFIG_VERSION, Config, activate_profile};
+
+fn parse_key(key: &str) -> Result<Vec<Key>> {
+    let keys = Key::parse(key).map_err(|e| anyhow::anyhow!("Invalid key '{}': {}", key, e.message()))?;
+
+    if keys.is_empty() {
+        anyhow::bail!("Invalid key '{}'", key);
+    }
+
+    Ok(keys)
+}
+
+fn read(config_file: &Path) -> Result<String> {
+    std::fs::read_to_string(config_file).with_context(|| format!("Failed to read '{}'", config_file.display()))
+}
+
+/// Content of the configuration file, as written
+pub fn show(config_file: &Path) -> Result<String> {
+    read(config_file)
+}
+
+/// Configuration with every default filled in, as seen from the active
+/// profile (`profile`, `FOREST_PROFILE` or detected), as TOML
+pub fn show_effective(config_file: &Path, profile: Option<String>) -> Result<String> {
+    let (config, _) = activate_profile(check(config_file)?, config_file, profile)?;
+
+    Ok(toml::to_string(&config)?)
+}
+
+/// Value of a dotted `key` (e.g. `general.base_dir`), defaults included, as
+/// seen from the active profile
+pub fn get(config_file: &Path, profile: Option<String>, key: &str) -> Result<String> {
+    let (config, _) = activate_profile(check(config_file)?, config_file, profile)?;
+    let mut current = toml::Value::try_from(&config)?;
+
+    for part in parse_key(key)? {
+        current = match current {
+            toml::Value::Table(mut table) => table.remove(part.get()),
+            _ => None,
+        }
+        .with_context(|| format!("Key '{}' is not set", key))?;
+    }
+
+    match current {
+        toml::Value::String(value) => Ok(value),
+        toml::Value::Table(table) => Ok(toml::to_string(&table)?.trim_end().to_string()),
+        other => Ok(other.to_string()),
+    }
+}
+
+/// Sets a dotted `key` to `value`, keeping the rest of the file (comments
+/// included) as is. `value` is read as TOML (`true`, `3`, `[".env"]`...),
+/// falling back to a plain string. Nothing is written if the result is invalid.
+/// Older files are migrated first so the edit lands on the current version
+pub fn set(config_file: &Path, key: &str, value: &str) -> Result<()> {
+    let content = if config_file.exists() {
+        let content = read(config_file)?;
+        migrate(config_file, &content)?.unwrap_or(content)
+    } else {
+        format!("version = {}\n", CONFIG_VERSION)
+    };
+    let mut document: DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse '{}'", config_file.display()))?;
+
+    let keys = parse_key(key)?;
+    let (last, parents) = keys.split_last().unwrap();
+    let mut table: &mut Table = document.as_table_mut();
+
+    for part in parents {
+        let entry = table.entry(part.get()).or_insert_with(|| {
+            let mut new_table = Table::new();
+            new_table.set_implicit(true);
+            Item::Table(new_table)
+        });
+        table = entry
+            .as_table_mut()
+            .with_context(|| format!("'{}' in '{}' is not a table", part.get(), key))?;
+    }
+
+    let parsed = value.parse::<Value>().unwrap_or_else(|_| Value::from(value));
+    table.insert(last.get(), Item::Value(parsed));
+
+    let updated = document.to_string();
+    validate(config_file, &updated)?;
+    std::fs::write(config_file, updated).with_context(|| format!("Failed to write '{}'", config_file.display()))?;
+
+    Ok(())
+}
+
+/// Validates the configuration file, returning warnings about keys forest ignores
+pub fn check_with_warnings(config_file: &Path) -> Result<(Config, Vec<String>)> {
+    let content = read(config_file)?;
+
+    Ok(validate(config_file, &content)?)
+}
+
+fn check(config_file: &Path) -> Result<Config> {
+    Ok(check_with_warnings(config_file)?.0)
+}
+
+/// Opens the configuration file in `$VISUAL`/`$EDITOR` (`vi` otherwise)
+pub fn edit(config_file: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    // The editor may come with arguments, e.g. "code --wait"
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$0\"", editor))
+        .arg(config_file)
+        .status()
+        .with_context(|| format!("Failed to start editor '{}'", editor))?;
+
+    if !status.success() {
+        anyhow::bail!("Editor '{}' exited with an error", editor);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const CONFIG: &str = r#"version = 1
+
+# Where everything lives
+[general]
+base_dir = "/srv/forest" # not the default
+exec = ["npm install"]
+
+[roots.api]
+copy = [".env.api"]
+"#;
+
+    fn config_file(temp_dir: &TempDir) -> std::path::PathBuf {
+        let file = temp_dir.path().join("config.toml");
+        fs::write(&file, CONFIG).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_get() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = config_file(&temp_dir);
+
+        assert_eq!(get(&file, None, "general.base_dir").unwrap(), "/srv/forest");
+        assert_eq!(get(&file, None, "general.on_error").unwrap(), "continue");
+        assert_eq!(get(&file, None, "general.exec").unwrap(), "[{ run = \"npm install\" }]");
+        assert_eq!(get(&file, None, "roots.api.inherit").unwrap(), "true");
+        assert!(get(&file, None, "general.nope").unwrap_err().to_string().contains("Key 'general.nope' is not set"));
+    }
+
+    #[test]
+    fn test_get_with_profile() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("config.toml");
+        fs::write(&file, "version = 1\n[general]\nbase_dir = \"/srv\"\n[profiles.work]\nbase_dir = \"/work\"\n").unwrap();
+
+        assert_eq!(get(&file, Some("work".to_string()), "general.base_dir").unwrap(), "/work");
+        assert!(show_effective(&file, Some("work".to_string())).unwrap().contains("base_dir = \"/work\""));
+        assert!(get(&file, Some("home".to_string()), "general.base_dir").unwrap_err().to_string().contains("Profile 'home' is not defined"));
+    }
+
+    #[test]
+    fn test_set_keeps_comments() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = config_file(&temp_dir);
+
+        set(&file, "general.on_error", "abort").unwrap();
+        set(&file, "roots.web.copy", "[\".env.web\"]").unwrap();
+        set(&file, "general.ports.range", "[4000, 4999]").unwrap();
+
+        let content = fs::read_to_string(&file).unwrap();
+        assert!(content.contains("# Where everything lives"));
+        assert!(content.contains("base_dir = \"/srv/forest\" # not the default"));
+        assert_eq!(get(&file, None, "general.on_error").unwrap(), "abort");
+        assert_eq!(get(&file, None, "roots.web.copy").unwrap(), "[{ mode = \"copy\", path = \".env.web\" }]");
+        assert_eq!(get(&file, None, "general.ports.per_tree").unwrap(), "1");
+    }
+
+    #[test]
+    fn test_set_rejects_invalid_values() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = config_file(&temp_dir);
+
+        let err = set(&file, "general.on_error", "explode").unwrap_err();
+
+        assert!(format!("{:#}", err).contains("unknown variant `explode`"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), CONFIG);
+    }
+
+    #[test]
+    fn test_set_creates_versioned_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("config.toml");
+
+        set(&file, "roots.api.copy", "[\".env.api\"]").unwrap();
+
+        let config = check(&file).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(config.roots["api"].inherit);
+    }
+
+    #[test]
+    fn test_set_migrates_unversioned_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("config.toml");
+        fs::write(&file, "[roots.api]\ncopy = [\".env.api\"]\n").unwrap();
+
+        set(&file, "general.base_dir", "/srv").unwrap();
+
+        let content = fs::read_to_string(&file).unwrap();
+        assert!(content.contains("version = 1"), "{}", content);
+        assert!(content.contains("inherit = false"), "{}", content);
+    }
+
+    #[test]
+    fn test_check_reports_unknown_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("config.toml");
+        fs::write(&file, "version = 1\n[general]\nbase_dirr = \"/srv\"\n").unwrap();
+
+        let (_, warnings) = check_with_warnings(&file).unwrap();
+
+        assert_eq!(warnings, vec!["general.base_dirr".to_string()]);
+    }
+
+    #[test]
+    fn test_show_effective_fills_defaults() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("config.toml");
+        fs::write(&file, "version = 1\n").unwrap();
+
+        let effective = show_effective(&file, None).unwrap();
+
+        assert!(effective.contains("base_dir = \"~/Projects\""));
+        assert!(effective.contains("on_error = \"continue\""));
+    }
+}
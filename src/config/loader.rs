@@ -1,42 +1,220 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use toml_edit::{DocumentMut, Item, value};
 
-use super::{Config, GeneralConfig};
-use crate::utils::path::{home_dir};
+use super::{CONFIG_VERSION, Config, GeneralConfig, RootConfig, RootPattern, default_copy};
+use crate::utils::cli_ui;
+use crate::utils::path::home_dir;
 
-fn default_copy() -> Vec<String> {
-    vec![".env".to_string(), ".envrc".to_string()]
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to access '{file}': {source}")]
+    Io {
+        file: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid configuration in '{file}' at line {line}, column {column}: {message}")]
+    Invalid {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("'{file}' uses configuration version {version}, but this forest only knows up to version {CONFIG_VERSION}. Please upgrade forest")]
+    UnsupportedVersion {
+        file: PathBuf,
+        version: i64,
+    },
+    #[error("Invalid root pattern '{key}' in '{file}': {message}")]
+    InvalidRootPattern {
+        file: PathBuf,
+        key: String,
+        message: String,
+    },
+    #[error("Configuration file '{0}' does not exist")]
+    Missing(PathBuf),
+    #[error("Could not determine home directory")]
+    HomeDir,
+    #[error("Failed to write configuration: {0}")]
+    Serialize(#[from] toml::ser::Error),
 }
 
-pub fn load(config_dir: PathBuf) -> Result<Config, Box<dyn std::error::Error>> {
-    let config_file = config_dir.join("config.toml");
+/// Name of the user's configuration file inside the config directory
+pub const CONFIG_FILE: &str = "config.toml";
 
+/// Reads `config_file`. A missing file is an error unless `create_default`
+/// is set, in which case a default configuration is written there
+pub fn load(config_file: &Path, create_default: bool) -> Result<Config, ConfigError> {
     if config_file.exists() {
-        let config_content = std::fs::read_to_string(config_file)?;
-        let config: Config = toml::from_str(&config_content)?;
+        let mut config_content = read(config_file)?;
 
+        if let Some(migrated) = migrate(config_file, &config_content)? {
+            // Read-only files (e.g. generated by home-manager) are migrated in memory only
+            let msg = match write(config_file, &migrated) {
+                Ok(()) => format!("Migrated '{}' to configuration version {}", config_file.display(), CONFIG_VERSION),
+                Err(err) => format!("Using '{}' as configuration version {} ({})", config_file.display(), CONFIG_VERSION, err),
+            };
+            eprintln!("{}", cli_ui::context_warn(&msg));
+            config_content = migrated;
+        }
+
+        let config = parse_reporting(config_file, &config_content)?;
+        check_root_patterns(config_file, &config)?;
         return Ok(config);
     }
 
-    let home_dir_string = home_dir()?.to_string_lossy().to_string();
+    if !create_default {
+        return Err(ConfigError::Missing(config_file.to_path_buf()));
+    }
+
+    let home_dir_string = home_dir().map_err(|_| ConfigError::HomeDir)?.to_string_lossy().to_string();
     let default_config = Config {
+        version: CONFIG_VERSION,
         general: GeneralConfig {
             base_dir: format!("{}/Projects", home_dir_string),
             copy: default_copy(),
-            exec: vec![],
+            ..Default::default()
         },
-        roots: HashMap::new(),
+        roots: Default::default(),
+        profiles: Default::default(),
     };
 
     let config_toml = toml::to_string(&default_config)?;
-    std::fs::write(config_file, config_toml)?;
+    write(config_file, &config_toml)?;
+
+    let msg = format!("Created default configuration at '{}'", config_file.display());
+    eprintln!("{}", cli_ui::context(&msg));
 
     Ok(default_config)
 }
 
+fn read(file: &Path) -> Result<String, ConfigError> {
+    std::fs::read_to_string(file).map_err(|source| ConfigError::Io { file: file.to_path_buf(), source })
+}
+
+fn write(file: &Path, content: &str) -> Result<(), ConfigError> {
+    std::fs::write(file, content).map_err(|source| ConfigError::Io { file: file.to_path_buf(), source })
+}
+
+/// `content` upgraded to `CONFIG_VERSION`, keeping comments and formatting.
+/// `None` when it is already up to date
+pub(super) fn migrate(file: &Path, content: &str) -> Result<Option<String>, ConfigError> {
+    let mut document: DocumentMut = content.parse().map_err(|e: toml_edit::TomlError| {
+        invalid(file, content, e.span(), e.message())
+    })?;
+
+    let version = document.get("version").and_then(Item::as_integer).unwrap_or(0);
+    if version > CONFIG_VERSION as i64 {
+        return Err(ConfigError::UnsupportedVersion { file: file.to_path_buf(), version });
+    }
+    if version == CONFIG_VERSION as i64 {
+        return Ok(None);
+    }
+
+    if version < 1 {
+        // Root settings used to replace the general ones instead of extending them
+        if let Some(roots) = document.get_mut("roots").and_then(Item::as_table_like_mut) {
+            for (_, root) in roots.iter_mut() {
+                if let Some(root) = root.as_table_like_mut()
+                    && !root.contains_key("inherit")
+                {
+                    root.insert("inherit", value(false));
+                }
+            }
+        }
+    }
+
+    document.insert("version", value(CONFIG_VERSION as i64));
+    Ok(Some(document.to_string()))
+}
+
+/// Checks `content` as the user's configuration without touching the file,
+/// returning it along with the keys forest would ignore
+pub fn validate(file: &Path, content: &str) -> Result<(Config, Vec<String>), ConfigError> {
+    let (config, unknown_keys) = match migrate(file, content)? {
+        Some(migrated) => parse(file, &migrated)?,
+        None => parse(file, content)?,
+    };
+    check_root_patterns(file, &config)?;
+
+    Ok((config, unknown_keys))
+}
+
+/// Makes sure glob and regex `[roots]` keys, profiles' included, compile
+fn check_root_patterns(file: &Path, config: &Config) -> Result<(), ConfigError> {
+    let keys = config.roots.keys().chain(config.profiles.values().flat_map(|profile| profile.roots.keys()));
+
+    for key in keys {
+        if let Some(Err(message)) = RootPattern::parse(key) {
+            return Err(ConfigError::InvalidRootPattern { file: file.to_path_buf(), key: key.clone(), message });
+        }
+    }
+
+    Ok(())
+}
+
+/// Deserializes `content`, warning about keys forest doesn't know
+fn parse_reporting<T: DeserializeOwned>(file: &Path, content: &str) -> Result<T, ConfigError> {
+    let (config, unknown_keys) = parse(file, content)?;
+
+    for key in unknown_keys {
+        let msg = format!("Unknown key '{}' in '{}' is ignored", key, file.display());
+        eprintln!("{}", cli_ui::warn(&msg));
+    }
+
+    Ok(config)
+}
+
+/// Deserializes `content`, also returning the keys that were ignored
+fn parse<T: DeserializeOwned>(file: &Path, content: &str) -> Result<(T, Vec<String>), ConfigError> {
+    let to_error = |e: toml::de::Error| invalid(file, content, e.span(), e.message());
+    let deserializer = toml::Deserializer::parse(content).map_err(to_error)?;
+
+    let mut unknown_keys = vec![];
+    let config = serde_ignored::deserialize(deserializer, |path| unknown_keys.push(path.to_string()))
+        .map_err(to_error)?;
+
+    Ok((config, unknown_keys))
+}
+
+fn invalid(file: &Path, content: &str, span: Option<std::ops::Range<usize>>, message: &str) -> ConfigError {
+    let offset = span.map_or(0, |span| span.start).min(content.len());
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+
+    ConfigError::Invalid {
+        file: file.to_path_buf(),
+        line,
+        column,
+        message: message.trim().to_string(),
+    }
+}
+
+/// Name of the configuration file a repository can commit at its top level
+pub const REPO_CONFIG_FILE: &str = "forest.toml";
+
+/// The repository's own `forest.toml`, holding the same settings as a
+/// `[roots.<name>]` table, along with the content it was parsed from.
+/// `None` when the repository doesn't have one
+pub fn load_repo(root_path: &Path) -> Result<Option<(RootConfig, String)>, ConfigError> {
+    let config_file = root_path.join(REPO_CONFIG_FILE);
+
+    if !config_file.exists() {
+        return Ok(None);
+    }
+
+    let config_content = read(&config_file)?;
+    let config = parse_reporting(&config_file, &config_content)?;
+
+    Ok(Some((config, config_content)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{CopyEntry, CopyMode, ExecCommand, OnError};
 
     #[test]
     fn test_load_creates_default() {
@@ -44,14 +222,14 @@ mod tests {
         let _ = std::fs::remove_dir_all(&temp_dir);
         std::fs::create_dir_all(&temp_dir).unwrap();
 
-        let result = load(temp_dir.clone());
+        let result = load(&temp_dir.join(CONFIG_FILE), true);
         assert!(result.is_ok());
 
         let config = result.unwrap();
 
         let home_dir_string = home_dir().unwrap().to_string_lossy().to_string();
         assert_eq!(config.general.base_dir, format!("{}/Projects", home_dir_string));
-        assert_eq!(config.general.copy, vec![".env".to_string(), ".envrc".to_string()]);
+        assert_eq!(config.general.copy, vec![".env".into(), ".envrc".into()]);
         assert!(config.general.exec.is_empty());
 
         let config_file = temp_dir.join("config.toml");
@@ -60,6 +238,17 @@ mod tests {
         std::fs::remove_dir_all(&temp_dir).unwrap();
     }
 
+    #[test]
+    fn test_load_missing_explicit_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("ci.toml");
+
+        let err = load(&config_file, false).unwrap_err();
+
+        assert!(matches!(err, ConfigError::Missing(_)));
+        assert!(!config_file.exists());
+    }
+
     #[test]
     fn test_load_reads_existing() {
         let temp_dir = std::env::temp_dir().join("sundial_test_load_reads_existing");
@@ -83,19 +272,181 @@ exec = ["exec repo2"]
 "#;
         std::fs::write(&config_file, custom_config_content).unwrap();
 
-        let result = load(temp_dir.clone());
+        let result = load(&temp_dir.join(CONFIG_FILE), true);
         assert!(result.is_ok());
 
         let config = result.unwrap();
 
         assert_eq!(config.general.base_dir, "/home/Custom");
-        assert_eq!(config.general.copy, vec![".env.custom"]);
-        assert_eq!(config.general.exec, vec!["exec custom"]);
-        assert_eq!(config.roots["repo1"].copy, vec![".env.repo1"]);
-        assert_eq!(config.roots["repo1"].exec, vec!["exec repo1"]);
-        assert_eq!(config.roots["repo2"].copy, vec![".env.repo2"]);
-        assert_eq!(config.roots["repo2"].exec, vec!["exec repo2"]);
+        assert_eq!(config.general.copy, vec![".env.custom".into()]);
+        assert_eq!(config.general.exec, vec!["exec custom".into()]);
+        assert_eq!(config.roots["repo1"].copy, vec![".env.repo1".into()]);
+        assert_eq!(config.roots["repo1"].exec, vec!["exec repo1".into()]);
+        assert_eq!(config.roots["repo2"].copy, vec![".env.repo2".into()]);
+        assert_eq!(config.roots["repo2"].exec, vec!["exec repo2".into()]);
 
         std::fs::remove_dir_all(&temp_dir).unwrap();
     }
+
+    #[test]
+    fn test_load_reads_tables() {
+        let temp_dir = std::env::temp_dir().join("forest_test_load_reads_tables");
+        let _ = std::fs::remove_dir_all(&temp_dir);
+        std::fs::create_dir_all(&temp_dir).unwrap();
+
+        let config_file = temp_dir.join("config.toml");
+        let custom_config_content = r#"
+[general]
+base_dir = "/home/Custom"
+copy = [".env", { path = "fixtures/", mode = "reflink" }]
+exec = ["echo plain", { run = "npm install", on_error = "abort" }, { name = "gems", run = "bundle install", needs = [], cwd = "api" }]
+on_error = "prompt"
+
+[roots]
+"#;
+        std::fs::write(&config_file, custom_config_content).unwrap();
+
+        let config = load(&temp_dir.join(CONFIG_FILE), true).unwrap();
+
+        assert_eq!(config.general.on_error, OnError::Prompt);
+        assert_eq!(config.general.copy[0], CopyEntry::from(".env"));
+        assert_eq!(config.general.copy[1].path, "fixtures/");
+        assert_eq!(config.general.copy[1].mode, CopyMode::Reflink);
+        assert_eq!(config.general.exec[0], ExecCommand::from("echo plain"));
+        assert_eq!(config.general.exec[1].run, "npm install");
+        assert_eq!(config.general.exec[1].on_error, Some(OnError::Abort));
+        assert_eq!(config.general.exec[2].name.as_deref(), Some("gems"));
+        assert_eq!(config.general.exec[2].needs, Some(vec![]));
+        assert_eq!(config.general.exec[2].cwd.as_deref(), Some("api"));
+
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_root_pattern() {
+        let err = validate(Path::new("config.toml"), "version = 1\n[roots.\"/svc-(/\"]\n").unwrap_err();
+
+        assert!(err.to_string().starts_with("Invalid root pattern '/svc-(/' in 'config.toml'"));
+    }
+
+    #[test]
+    fn test_load_repo() {
+        let root = tempfile::TempDir::new().unwrap();
+
+        let missing = load_repo(root.path()).unwrap();
+        std::fs::write(root.path().join(REPO_CONFIG_FILE), r#"
+copy = [".env.example"]
+exec = ["bin/setup"]
+
+[hooks]
+post_create = ["bin/dev --detach"]
+"#).unwrap();
+        let (config, content) = load_repo(root.path()).unwrap().unwrap();
+
+        assert!(missing.is_none());
+        assert!(content.contains("bin/dev --detach"));
+        assert_eq!(config.copy, vec![".env.example".into()]);
+        assert_eq!(config.exec, vec![ExecCommand::from("bin/setup")]);
+        assert_eq!(config.hooks.post_create, vec![ExecCommand::from("bin/dev --detach")]);
+    }
+
+    #[test]
+    fn test_load_repo_invalid() {
+        let root = tempfile::TempDir::new().unwrap();
+        std::fs::write(root.path().join(REPO_CONFIG_FILE), "copy = 3").unwrap();
+
+        let err = load_repo(root.path()).unwrap_err();
+
+        assert!(err.to_string().contains("forest.toml' at line 1, column 8"));
+    }
+
+    #[test]
+    fn test_load_without_optional_tables() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("config.toml"), "version = 1\n[general]\nbase_dir = \"/srv\"\n").unwrap();
+
+        let config = load(&temp_dir.path().join(CONFIG_FILE), true).unwrap();
+
+        assert_eq!(config.general.base_dir, "/srv");
+        assert_eq!(config.general.copy, vec![".env".into(), ".envrc".into()]);
+        assert!(config.general.exec.is_empty());
+        assert!(config.roots.is_empty());
+    }
+
+    #[test]
+    fn test_load_reports_position() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("config.toml"), "version = 1\n[general]\non_error = \"explode\"\n").unwrap();
+
+        let err = load(&temp_dir.path().join(CONFIG_FILE), true).unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.contains("at line 3, column 12"), "{}", message);
+        assert!(message.contains("unknown variant `explode`"), "{}", message);
+    }
+
+    #[test]
+    fn test_load_rejects_newer_version() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("config.toml"), "version = 99\n").unwrap();
+
+        let err = load(&temp_dir.path().join(CONFIG_FILE), true).unwrap_err();
+
+        assert!(matches!(err, ConfigError::UnsupportedVersion { version: 99, .. }));
+    }
+
+    #[test]
+    fn test_load_migrates_unversioned_config() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.toml");
+        std::fs::write(&config_file, r#"# my forest
+[general]
+base_dir = "/srv" # where everything lives
+
+[roots.api]
+copy = [".env.api"]
+
+[roots.web]
+inherit = true
+"#).unwrap();
+
+        let config = load(&temp_dir.path().join(CONFIG_FILE), true).unwrap();
+        let migrated = std::fs::read_to_string(&config_file).unwrap();
+
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(!config.roots["api"].inherit);
+        assert!(config.roots["web"].inherit);
+        assert!(migrated.contains("# my forest"));
+        assert!(migrated.contains("base_dir = \"/srv\" # where everything lives"));
+        assert!(migrated.contains("version = 1"));
+    }
+
+    #[test]
+    fn test_parse_collects_unknown_keys() {
+        let content = "[general]\nbase_dir = \"/srv\"\ncopyy = [\".env\"]\n\n[roots.api]\nexce = []\n";
+
+        let (_, unknown_keys): (Config, Vec<String>) = parse(Path::new("config.toml"), content).unwrap();
+
+        assert_eq!(unknown_keys, vec!["general.copyy".to_string(), "roots.api.exce".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_collects_unknown_keys_in_entries() {
+        let content = "[general]\nexec = [\"make\", { run = \"make test\", neds = [\"make\"] }]\ncopy = [{ path = \".env\", mod = \"symlink\" }]\n";
+
+        let (config, unknown_keys): (Config, Vec<String>) = parse(Path::new("config.toml"), content).unwrap();
+
+        assert_eq!(config.general.exec.len(), 2);
+        assert_eq!(unknown_keys, vec!["general.copy.0.mod".to_string(), "general.exec.1.neds".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_names_missing_field_in_entries() {
+        let content = "[general]\nexec = [{ rnu = \"make\" }]\n";
+
+        let err = parse::<Config>(Path::new("config.toml"), content).unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.contains("missing field `run`"), "{}", message);
+    }
 }
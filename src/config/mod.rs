@@ -1,24 +1,460 @@
-use std::collections::HashMap;
-use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::marker::PhantomData;
+use schemars::JsonSchema;
+use serde::de::{self, value::MapAccessDeserializer, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Format version of `config.toml` this forest writes. Older files are
+/// migrated when loaded
+pub const CONFIG_VERSION: u32 = 1;
+
+/// The user's configuration file, `config.toml`
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub struct Config {
+    /// Files without it predate versioning and count as version 0
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
     pub general: GeneralConfig,
+    #[serde(default)]
+    pub roots: HashMap<String, RootConfig>,
+    /// Separate workspaces, selected with `--profile`, `FOREST_PROFILE` or
+    /// by running forest inside their `base_dir`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+/// A workspace with its own `base_dir`. The settings it sets replace the
+/// `general` ones, and its roots the same-named top-level ones, while it is active
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct ProfileConfig {
+    /// Directory holding this profile's `roots/` and `trees/`
+    pub base_dir: String,
+    #[serde(default, deserialize_with = "optional_string_or_table", skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Vec<StringOrTable<CopyEntry>>>")]
+    pub copy: Option<Vec<CopyEntry>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copy_ignored: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "optional_string_or_table", skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Vec<StringOrTable<TemplateEntry>>>")]
+    pub templates: Option<Vec<TemplateEntry>>,
+    #[serde(default, deserialize_with = "optional_string_or_table", skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Vec<StringOrTable<ExecCommand>>>")]
+    pub exec: Option<Vec<ExecCommand>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<OnError>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ports: Option<PortsConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<Hooks>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree_name_template: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree_name_normalization: Option<NameNormalization>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch_template: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub roots: HashMap<String, RootConfig>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub struct GeneralConfig {
+    /// Directory holding `roots/` and `trees/`
+    #[serde(default = "default_base_dir")]
     pub base_dir: String,
-    pub copy: Vec<String>,
-    pub exec: Vec<String>,
+    /// Files, directories or glob patterns copied from the root into new trees
+    #[serde(default = "default_copy", deserialize_with = "string_or_table")]
+    #[schemars(with = "Vec<StringOrTable<CopyEntry>>")]
+    pub copy: Vec<CopyEntry>,
+    /// Patterns of git-ignored files in the root to copy into new trees
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub copy_ignored: Vec<String>,
+    /// Files rendered from the root into new trees
+    #[serde(default, deserialize_with = "string_or_table", skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<StringOrTable<TemplateEntry>>")]
+    pub templates: Vec<TemplateEntry>,
+    /// Commands run in new trees
+    #[serde(default, deserialize_with = "string_or_table")]
+    #[schemars(with = "Vec<StringOrTable<ExecCommand>>")]
+    pub exec: Vec<ExecCommand>,
+    /// What to do when a command in `exec` fails
+    #[serde(default)]
+    pub on_error: OnError,
+    /// Ports handed out to every tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ports: Option<PortsConfig>,
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// Directory of new trees inside `trees/`: `{root}` and `{branch}` are
+    /// replaced, and `/` nests directories (e.g. "{root}/{branch}")
+    #[serde(default = "default_tree_name_template")]
+    pub tree_name_template: String,
+    /// How branch names are turned into `{branch}`
+    #[serde(default)]
+    pub tree_name_normalization: NameNormalization,
+    /// Turns what `forest trees create` gets into a branch name, e.g.
+    /// "{user}/{ticket}-{slug}"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch_template: Option<String>,
+}
+
+fn default_base_dir() -> String {
+    "~/Projects".to_string()
+}
+
+pub fn default_copy() -> Vec<CopyEntry> {
+    vec![".env".into(), ".envrc".into()]
+}
+
+impl Default for GeneralConfig {
+    fn default() -> Self {
+        Self {
+            base_dir: default_base_dir(),
+            copy: default_copy(),
+            copy_ignored: vec![],
+            templates: vec![],
+            exec: vec![],
+            on_error: OnError::default(),
+            ports: None,
+            hooks: Hooks::default(),
+            tree_name_template: default_tree_name_template(),
+            tree_name_normalization: NameNormalization::default(),
+            branch_template: None,
+        }
+    }
+}
+
+fn default_tree_name_template() -> String {
+    "{root}--{branch}".to_string()
+}
+
+/// Runs of characters other than letters, digits, `-` and `_` in a branch
+/// name become `replacement`, so it can be used in a directory name
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct NameNormalization {
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+    #[serde(default)]
+    pub lowercase: bool,
+}
+
+fn default_replacement() -> String {
+    "--".to_string()
+}
+
+impl Default for NameNormalization {
+    fn default() -> Self {
+        Self { replacement: default_replacement(), lowercase: false }
+    }
+}
+
+/// Range from which every tree gets its own block of ports
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct PortsConfig {
+    /// First and last port (inclusive) forest may hand out
+    pub range: [u16; 2],
+    /// How many ports each tree gets
+    #[serde(default = "default_per_tree")]
+    pub per_tree: u16,
+}
+
+fn default_per_tree() -> u16 {
+    1
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Per-root settings. Lists extend the ones in `general` unless
+/// `inherit = false` or the key is listed in `replace`
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub struct RootConfig {
-    pub copy: Vec<String>,
-    pub exec: Vec<String>,
+    /// Whether `general` settings apply to this root at all
+    #[serde(default = "default_inherit")]
+    pub inherit: bool,
+    /// Keys whose `general` value this root replaces instead of extending
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replace: Vec<MergeKey>,
+    #[serde(default, deserialize_with = "string_or_table")]
+    #[schemars(with = "Vec<StringOrTable<CopyEntry>>")]
+    pub copy: Vec<CopyEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub copy_ignored: Vec<String>,
+    #[serde(default, deserialize_with = "string_or_table", skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<StringOrTable<TemplateEntry>>")]
+    pub templates: Vec<TemplateEntry>,
+    #[serde(default, deserialize_with = "string_or_table")]
+    #[schemars(with = "Vec<StringOrTable<ExecCommand>>")]
+    pub exec: Vec<ExecCommand>,
+    /// Extra environment variables for commands run against this root
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+}
+
+fn default_inherit() -> bool {
+    true
+}
+
+impl Default for RootConfig {
+    fn default() -> Self {
+        Self {
+            inherit: default_inherit(),
+            replace: vec![],
+            copy: vec![],
+            copy_ignored: vec![],
+            templates: vec![],
+            exec: vec![],
+            env: BTreeMap::new(),
+            hooks: Hooks::default(),
+        }
+    }
+}
+
+/// Settings a root can replace rather than extend
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeKey {
+    Copy,
+    CopyIgnored,
+    Templates,
+    Exec,
+    Hooks,
+}
+
+/// Commands run at specific points of a root's or tree's life.
+/// `pre_*` hooks abort the operation when they fail, unless a command
+/// says otherwise through its own `on_error`
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, JsonSchema)]
+pub struct Hooks {
+    /// In the root, before the tree is created
+    #[serde(default, deserialize_with = "string_or_table", skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<StringOrTable<ExecCommand>>")]
+    pub pre_create: Vec<ExecCommand>,
+    /// In the tree, after it has been created and set up
+    #[serde(default, deserialize_with = "string_or_table", skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<StringOrTable<ExecCommand>>")]
+    pub post_create: Vec<ExecCommand>,
+    /// In the tree, before it is deleted
+    #[serde(default, deserialize_with = "string_or_table", skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<StringOrTable<ExecCommand>>")]
+    pub pre_delete: Vec<ExecCommand>,
+    /// In the root, after the tree has been deleted
+    #[serde(default, deserialize_with = "string_or_table", skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<StringOrTable<ExecCommand>>")]
+    pub post_delete: Vec<ExecCommand>,
+    /// In the root, after it has been cloned
+    #[serde(default, deserialize_with = "string_or_table", skip_serializing_if = "Vec::is_empty")]
+    #[schemars(with = "Vec<StringOrTable<ExecCommand>>")]
+    pub post_clone: Vec<ExecCommand>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.pre_create.is_empty()
+            && self.post_create.is_empty()
+            && self.pre_delete.is_empty()
+            && self.post_delete.is_empty()
+            && self.post_clone.is_empty()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct CopyEntry {
+    pub path: String,
+    #[serde(default)]
+    pub mode: CopyMode,
+}
+
+impl From<String> for CopyEntry {
+    fn from(path: String) -> Self {
+        Self { path, mode: CopyMode::default() }
+    }
+}
+
+impl From<&str> for CopyEntry {
+    fn from(path: &str) -> Self {
+        Self::from(path.to_string())
+    }
+}
+
+/// How a `copy` entry ends up in the tree
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyMode {
+    /// Independent copy of the file
+    #[default]
+    Copy,
+    /// Symbolic link pointing back to the root's file
+    Symlink,
+    /// Hard link sharing the root's file
+    Hardlink,
+    /// Copy-on-write clone (needs filesystem support)
+    Reflink,
+}
+
+/// A file (or directory of files) rendered into the tree with `{{variable}}`
+/// placeholders replaced
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct TemplateEntry {
+    /// Relative to the root, or an absolute path to a template kept elsewhere
+    pub source: String,
+    /// Relative to the tree. Defaults to `source` without a trailing `.tmpl`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+}
+
+impl TemplateEntry {
+    pub fn destination(&self) -> String {
+        match &self.destination {
+            Some(destination) => destination.clone(),
+            None => self.source.strip_suffix(".tmpl").unwrap_or(&self.source).to_string(),
+        }
+    }
+}
+
+impl From<String> for TemplateEntry {
+    fn from(source: String) -> Self {
+        Self { source, destination: None }
+    }
+}
+
+impl From<&str> for TemplateEntry {
+    fn from(source: &str) -> Self {
+        Self::from(source.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct ExecCommand {
+    /// How other commands refer to this one in `needs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub run: String,
+    /// Names of the commands that must finish first. Without it, a command
+    /// waits for every command listed before it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub needs: Option<Vec<String>>,
+    /// Directory to run in, relative to the tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Only run when this path exists in the tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub if_exists: Option<String>,
+    /// Only run when this file differs between the root and the tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub if_changed: Option<String>,
+    /// Skip when this environment variable is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unless_env: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<OnError>,
+    /// Run detached once the tree is ready, logging to `.forest/setup.log`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub background: bool,
+}
+
+impl From<String> for ExecCommand {
+    fn from(run: String) -> Self {
+        Self {
+            name: None,
+            run,
+            needs: None,
+            cwd: None,
+            if_exists: None,
+            if_changed: None,
+            unless_env: None,
+            on_error: None,
+            background: false,
+        }
+    }
+}
+
+impl From<&str> for ExecCommand {
+    fn from(run: &str) -> Self {
+        Self::from(run.to_string())
+    }
+}
+
+/// What to do when a setup command fails
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OnError {
+    /// Stop running setup commands and fail
+    Abort,
+    /// Report the failure and keep going
+    #[default]
+    Continue,
+    /// Ask whether to keep going
+    Prompt,
+}
+
+/// List entry written either as a plain string or as a table
+#[derive(JsonSchema)]
+#[serde(untagged)]
+enum StringOrTable<T> {
+    Plain(String),
+    Table(T),
+}
+
+// Deserialized by hand rather than untagged so unknown keys inside tables are
+// still reported and table errors name the offending field
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for StringOrTable<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> Visitor<'de> for EntryVisitor<T> {
+            type Value = StringOrTable<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string or a table")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(StringOrTable::Plain(value.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                T::deserialize(MapAccessDeserializer::new(map)).map(StringOrTable::Table)
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor(PhantomData))
+    }
+}
+
+/// Accepts list entries written either as a plain string or as a table
+fn string_or_table<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + From<String>,
+{
+    let entries = Vec::<StringOrTable<T>>::deserialize(deserializer)?;
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            StringOrTable::Plain(value) => T::from(value),
+            StringOrTable::Table(value) => value,
+        })
+        .collect())
+}
+
+fn optional_string_or_table<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + From<String>,
+{
+    string_or_table(deserializer).map(Some)
 }
 
+pub mod commands;
 mod loader;
-pub use loader::load as load_config;
+mod pattern;
+mod profile;
+mod resolve;
+pub mod schema;
+pub use loader::{CONFIG_FILE, load as load_config};
+pub use loader::{REPO_CONFIG_FILE, load_repo as load_repo_config};
+pub use pattern::RootPattern;
+pub use profile::activate as activate_profile;
+pub use resolve::{ResolvedConfig, resolve as resolve_config};
@@ -0,0 +1,65 @@
+use regex::Regex;
+
+/// A `[roots]` key standing for every root whose name matches it
+#[derive(Debug)]
+pub enum RootPattern {
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl RootPattern {
+    /// Pattern a `[roots]` key stands for, or `None` for a plain root name.
+    /// Keys written `/.../` are regexes, keys with `*`, `?` or `[` are globs
+    pub fn parse(key: &str) -> Option<Result<Self, String>> {
+        if let Some(regex) = key.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+            return Some(Regex::new(regex).map(Self::Regex).map_err(|e| e.to_string()));
+        }
+
+        if key.contains(['*', '?', '[']) {
+            return Some(glob::Pattern::new(key).map(Self::Glob).map_err(|e| e.to_string()));
+        }
+
+        None
+    }
+
+    pub fn matches(&self, root: &str) -> bool {
+        match self {
+            Self::Glob(pattern) => pattern.matches(root),
+            Self::Regex(regex) => regex.is_match(root),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(key: &str, root: &str) -> bool {
+        RootPattern::parse(key).unwrap().unwrap().matches(root)
+    }
+
+    #[test]
+    fn test_plain_names() {
+        assert!(RootPattern::parse("api").is_none());
+        assert!(RootPattern::parse("my.repo").is_none());
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(matches("svc-*", "svc-billing"));
+        assert!(matches("svc-?", "svc-a"));
+        assert!(!matches("svc-*", "api"));
+    }
+
+    #[test]
+    fn test_regex() {
+        assert!(matches("/^svc-(billing|users)$/", "svc-users"));
+        assert!(!matches("/^svc-(billing|users)$/", "svc-search"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(RootPattern::parse("/svc-(/").unwrap().is_err());
+        assert!(RootPattern::parse("svc-[").unwrap().is_err());
+    }
+}
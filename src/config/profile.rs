@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use super::Config;
+use crate::utils::path::expand as expand_path;
+
+/// `config` as seen from profile `name`, or `None` if there is no such profile
+pub fn apply(mut config: Config, name: &str) -> Option<Config> {
+    let profile = config.profiles.get(name)?.clone();
+    let general = &mut config.general;
+
+    general.base_dir = profile.base_dir;
+    if let Some(copy) = profile.copy {
+        general.copy = copy;
+    }
+    if let Some(copy_ignored) = profile.copy_ignored {
+        general.copy_ignored = copy_ignored;
+    }
+    if let Some(templates) = profile.templates {
+        general.templates = templates;
+    }
+    if let Some(exec) = profile.exec {
+        general.exec = exec;
+    }
+    if let Some(on_error) = profile.on_error {
+        general.on_error = on_error;
+    }
+    if profile.ports.is_some() {
+        general.ports = profile.ports;
+    }
+    if let Some(hooks) = profile.hooks {
+        general.hooks = hooks;
+    }
+    if let Some(tree_name_template) = profile.tree_name_template {
+        general.tree_name_template = tree_name_template;
+    }
+    if let Some(tree_name_normalization) = profile.tree_name_normalization {
+        general.tree_name_normalization = tree_name_normalization;
+    }
+    if profile.branch_template.is_some() {
+        general.branch_template = profile.branch_template;
+    }
+    config.roots.extend(profile.roots);
+
+    Some(config)
+}
+
+/// `config` as seen from the active profile, along with its name: `flag`,
+/// else `FOREST_PROFILE`, else the profile whose `base_dir` contains the
+/// current directory
+pub fn activate(config: Config, config_file: &Path, flag: Option<String>) -> Result<(Config, Option<String>)> {
+    let config_dir = config_file.parent().unwrap_or(Path::new(""));
+    let name = flag
+        .or_else(|| std::env::var("FOREST_PROFILE").ok().filter(|value| !value.is_empty()))
+        .or_else(|| detect(&config, &std::env::current_dir().ok()?, config_dir));
+
+    let Some(name) = name else {
+        return Ok((config, None));
+    };
+    let config = apply(config, &name)
+        .with_context(|| format!("Profile '{}' is not defined in '{}'", name, config_file.display()))?;
+
+    Ok((config, Some(name)))
+}
+
+/// Profile whose `base_dir` contains `dir`, the deepest one when they nest.
+/// Relative `base_dir`s are relative to `config_dir`
+pub fn detect(config: &Config, dir: &Path, config_dir: &Path) -> Option<String> {
+    config
+        .profiles
+        .iter()
+        .filter_map(|(name, profile)| Some((name, expand_path(&profile.base_dir, config_dir).ok()?)))
+        .filter(|(_, base_dir)| dir.starts_with(base_dir))
+        .max_by_key(|(_, base_dir)| base_dir.components().count())
+        .map(|(name, _)| name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{OnError, ProfileConfig, RootConfig};
+    use std::collections::{BTreeMap, HashMap};
+
+    fn profile(base_dir: &str) -> ProfileConfig {
+        ProfileConfig {
+            base_dir: base_dir.to_string(),
+            copy: None,
+            copy_ignored: None,
+            templates: None,
+            exec: None,
+            on_error: None,
+            ports: None,
+            hooks: None,
+            tree_name_template: None,
+            tree_name_normalization: None,
+            branch_template: None,
+            roots: HashMap::new(),
+        }
+    }
+
+    fn config() -> Config {
+        let work = ProfileConfig {
+            exec: Some(vec!["make bootstrap".into()]),
+            on_error: Some(OnError::Abort),
+            roots: HashMap::from([(
+                "api".to_string(),
+                RootConfig { exec: vec!["bin/setup".into()], ..Default::default() },
+            )]),
+            ..profile("/srv/work")
+        };
+
+        Config {
+            version: crate::config::CONFIG_VERSION,
+            general: Default::default(),
+            roots: HashMap::from([
+                ("api".to_string(), RootConfig::default()),
+                ("dotfiles".to_string(), RootConfig::default()),
+            ]),
+            profiles: BTreeMap::from([
+                ("work".to_string(), work),
+                ("client".to_string(), profile("/srv/work/client")),
+                ("personal".to_string(), profile("home")),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let config = apply(config(), "work").unwrap();
+
+        assert_eq!(config.general.base_dir, "/srv/work");
+        assert_eq!(config.general.exec, vec!["make bootstrap".into()]);
+        assert_eq!(config.general.on_error, OnError::Abort);
+        assert_eq!(config.general.copy, vec![".env".into(), ".envrc".into()]);
+        assert_eq!(config.roots["api"].exec, vec!["bin/setup".into()]);
+        assert!(config.roots.contains_key("dotfiles"));
+    }
+
+    #[test]
+    fn test_apply_unknown_profile() {
+        assert!(apply(config(), "nope").is_none());
+    }
+
+    #[test]
+    fn test_detect() {
+        let config = config();
+        let config_dir = Path::new("/etc/forest");
+
+        assert_eq!(detect(&config, Path::new("/srv/work/trees/api/main"), config_dir), Some("work".to_string()));
+        assert_eq!(detect(&config, Path::new("/srv/work/client/roots"), config_dir), Some("client".to_string()));
+        assert_eq!(detect(&config, Path::new("/etc/forest/home/roots"), config_dir), Some("personal".to_string()));
+        assert_eq!(detect(&config, Path::new("/srv/workshop"), config_dir), None);
+    }
+}
@@ -0,0 +1,276 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::{Config, CopyEntry, ExecCommand, Hooks, MergeKey, OnError, RootConfig, RootPattern, TemplateEntry};
+
+/// Setup settings that apply to a single root
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResolvedConfig {
+    pub copy: Vec<CopyEntry>,
+    pub copy_ignored: Vec<String>,
+    pub templates: Vec<TemplateEntry>,
+    pub exec: Vec<ExecCommand>,
+    pub on_error: OnError,
+    pub env: BTreeMap<String, String>,
+    pub hooks: Hooks,
+}
+
+/// `general` settings extended with the root's ones: first those of the
+/// repository's own `forest.toml` (`repo`), then the user's (see `user_root_config`).
+/// Entries a later layer redefines (same copy path, template source or
+/// command) keep that layer's version, in that layer's position
+pub fn resolve(config: &Config, root: &str, repo: Option<&RootConfig>) -> ResolvedConfig {
+    let general = &config.general;
+    let root_config = match (repo, user_root_config(config, root)) {
+        (Some(repo), Some(user)) => layer(repo, &user),
+        (Some(only), None) => only.clone(),
+        (None, Some(only)) => only,
+        (None, None) => RootConfig::default(),
+    };
+
+    let inherits = |key: MergeKey| root_config.inherit && !root_config.replace.contains(&key);
+
+    ResolvedConfig {
+        copy: merge(&general.copy, &root_config.copy, inherits(MergeKey::Copy), |entry| entry.path.clone()),
+        copy_ignored: merge(
+            &general.copy_ignored,
+            &root_config.copy_ignored,
+            inherits(MergeKey::CopyIgnored),
+            |pattern| pattern.clone(),
+        ),
+        templates: merge(
+            &general.templates,
+            &root_config.templates,
+            inherits(MergeKey::Templates),
+            |template| template.source.clone(),
+        ),
+        exec: merge(&general.exec, &root_config.exec, inherits(MergeKey::Exec), |command| command.clone()),
+        on_error: general.on_error,
+        env: root_config.env.clone(),
+        hooks: merge_hooks(&general.hooks, &root_config.hooks, inherits(MergeKey::Hooks)),
+    }
+}
+
+/// The user's settings for `root`: those of every pattern key matching it
+/// (in key order), then its exact `[roots.<root>]` entry on top
+fn user_root_config(config: &Config, root: &str) -> Option<RootConfig> {
+    let mut matching: Vec<(&String, &RootConfig)> = config
+        .roots
+        .iter()
+        .filter(|(key, _)| RootPattern::parse(key).is_some_and(|pattern| pattern.is_ok_and(|p| p.matches(root))))
+        .collect();
+    matching.sort_by_key(|(key, _)| *key);
+    matching.extend(config.roots.get_key_value(root));
+
+    matching
+        .into_iter()
+        .map(|(_, root_config)| root_config.clone())
+        .reduce(|under, over| layer(&under, &over))
+}
+
+/// `user` root settings on top of the repository's (or a broader pattern's) ones
+fn layer(repo: &RootConfig, user: &RootConfig) -> RootConfig {
+    let mut replace = repo.replace.clone();
+    replace.extend(user.replace.iter().filter(|key| !repo.replace.contains(key)));
+
+    let mut env = repo.env.clone();
+    env.extend(user.env.clone());
+
+    RootConfig {
+        inherit: repo.inherit && user.inherit,
+        replace,
+        copy: merge(&repo.copy, &user.copy, true, |entry| entry.path.clone()),
+        copy_ignored: merge(&repo.copy_ignored, &user.copy_ignored, true, |pattern| pattern.clone()),
+        templates: merge(&repo.templates, &user.templates, true, |template| template.source.clone()),
+        exec: merge(&repo.exec, &user.exec, true, |command| command.clone()),
+        env,
+        hooks: merge_hooks(&repo.hooks, &user.hooks, true),
+    }
+}
+
+fn merge_hooks(base: &Hooks, over: &Hooks, inherit: bool) -> Hooks {
+    let merge_commands = |base: &Vec<ExecCommand>, over: &Vec<ExecCommand>| {
+        merge(base, over, inherit, |command| command.clone())
+    };
+
+    Hooks {
+        pre_create: merge_commands(&base.pre_create, &over.pre_create),
+        post_create: merge_commands(&base.post_create, &over.post_create),
+        pre_delete: merge_commands(&base.pre_delete, &over.pre_delete),
+        post_delete: merge_commands(&base.post_delete, &over.post_delete),
+        post_clone: merge_commands(&base.post_clone, &over.post_clone),
+    }
+}
+
+fn merge<T: Clone, K: PartialEq>(general: &[T], root: &[T], inherit: bool, key: impl Fn(&T) -> K) -> Vec<T> {
+    if !inherit {
+        return root.to_vec();
+    }
+
+    let root_keys: Vec<K> = root.iter().map(&key).collect();
+
+    general
+        .iter()
+        .filter(|entry| !root_keys.contains(&key(entry)))
+        .chain(root.iter())
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CopyMode, GeneralConfig};
+    use std::collections::HashMap;
+
+    fn config(root: RootConfig) -> Config {
+        Config {
+            version: crate::config::CONFIG_VERSION,
+            general: GeneralConfig {
+                base_dir: "/tmp/forest".to_string(),
+                copy: vec![".env".into(), ".envrc".into()],
+                copy_ignored: vec!["*.local".to_string()],
+                templates: vec![],
+                exec: vec!["npm install".into()],
+                on_error: OnError::Abort,
+                ports: None,
+                hooks: Hooks { post_create: vec!["echo general".into()], ..Default::default() },
+                ..Default::default()
+            },
+            roots: HashMap::from([("api".to_string(), root)]),
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_without_root_config() {
+        let config = config(RootConfig::default());
+
+        let resolved = resolve(&config, "unknown", None);
+
+        assert_eq!(resolved.copy, config.general.copy);
+        assert_eq!(resolved.exec, config.general.exec);
+        assert_eq!(resolved.on_error, OnError::Abort);
+        assert!(resolved.env.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_extends_general() {
+        let config = config(RootConfig {
+            copy: vec![CopyEntry { path: ".env".to_string(), mode: CopyMode::Symlink }, "config/master.key".into()],
+            exec: vec!["bundle install".into()],
+            hooks: Hooks { post_create: vec!["echo root".into()], ..Default::default() },
+            ..Default::default()
+        });
+
+        let resolved = resolve(&config, "api", None);
+
+        assert_eq!(
+            resolved.copy,
+            vec![
+                ".envrc".into(),
+                CopyEntry { path: ".env".to_string(), mode: CopyMode::Symlink },
+                "config/master.key".into(),
+            ]
+        );
+        assert_eq!(resolved.copy_ignored, vec!["*.local".to_string()]);
+        assert_eq!(resolved.exec, vec!["npm install".into(), "bundle install".into()]);
+        assert_eq!(resolved.hooks.post_create, vec!["echo general".into(), "echo root".into()]);
+    }
+
+    #[test]
+    fn test_resolve_replace_keys() {
+        let config = config(RootConfig {
+            replace: vec![MergeKey::Exec, MergeKey::Hooks],
+            copy: vec!["config/master.key".into()],
+            exec: vec!["bundle install".into()],
+            ..Default::default()
+        });
+
+        let resolved = resolve(&config, "api", None);
+
+        assert_eq!(resolved.copy, vec![".env".into(), ".envrc".into(), "config/master.key".into()]);
+        assert_eq!(resolved.exec, vec!["bundle install".into()]);
+        assert!(resolved.hooks.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_without_inheritance() {
+        let config = config(RootConfig {
+            inherit: false,
+            copy: vec!["config/master.key".into()],
+            ..Default::default()
+        });
+
+        let resolved = resolve(&config, "api", None);
+
+        assert_eq!(resolved.copy, vec!["config/master.key".into()]);
+        assert!(resolved.copy_ignored.is_empty());
+        assert!(resolved.exec.is_empty());
+        assert!(resolved.hooks.is_empty());
+        assert_eq!(resolved.on_error, OnError::Abort);
+    }
+
+    #[test]
+    fn test_resolve_with_repo_config() {
+        let config = config(RootConfig {
+            copy: vec![CopyEntry { path: "config/master.key".to_string(), mode: CopyMode::Symlink }],
+            env: BTreeMap::from([("RAILS_ENV".to_string(), "test".to_string())]),
+            ..Default::default()
+        });
+        let repo = RootConfig {
+            copy: vec!["config/master.key".into(), ".env.example".into()],
+            exec: vec!["bin/setup".into()],
+            env: BTreeMap::from([
+                ("RAILS_ENV".to_string(), "development".to_string()),
+                ("PORT".to_string(), "3000".to_string()),
+            ]),
+            ..Default::default()
+        };
+
+        let resolved = resolve(&config, "api", Some(&repo));
+
+        assert_eq!(
+            resolved.copy,
+            vec![
+                ".env".into(),
+                ".envrc".into(),
+                ".env.example".into(),
+                CopyEntry { path: "config/master.key".to_string(), mode: CopyMode::Symlink },
+            ]
+        );
+        assert_eq!(resolved.exec, vec!["npm install".into(), "bin/setup".into()]);
+        assert_eq!(resolved.env["RAILS_ENV"], "test");
+        assert_eq!(resolved.env["PORT"], "3000");
+    }
+
+    #[test]
+    fn test_resolve_with_root_patterns() {
+        let mut config = config(RootConfig {
+            exec: vec!["bin/api-setup".into()],
+            env: BTreeMap::from([("PORT".to_string(), "4000".to_string())]),
+            ..Default::default()
+        });
+        config.roots.insert(
+            "*".to_string(),
+            RootConfig { copy: vec![".tool-versions".into()], ..Default::default() },
+        );
+        config.roots.insert(
+            "/^(api|web)$/".to_string(),
+            RootConfig {
+                exec: vec!["make deps".into()],
+                env: BTreeMap::from([("PORT".to_string(), "3000".to_string())]),
+                ..Default::default()
+            },
+        );
+
+        let api = resolve(&config, "api", None);
+        let worker = resolve(&config, "worker", None);
+
+        assert_eq!(api.copy, vec![".env".into(), ".envrc".into(), ".tool-versions".into()]);
+        assert_eq!(api.exec, vec!["npm install".into(), "make deps".into(), "bin/api-setup".into()]);
+        assert_eq!(api.env["PORT"], "4000");
+        assert_eq!(worker.exec, vec!["npm install".into()]);
+        assert_eq!(worker.copy, vec![".env".into(), ".envrc".into(), ".tool-versions".into()]);
+    }
+}
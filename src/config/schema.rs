@@ -0,0 +1,36 @@
+use schemars::schema_for;
+
+use super::{Config, RootConfig};
+
+/// JSON Schema of the user's `config.toml`
+pub fn config() -> String {
+    to_json(schema_for!(Config))
+}
+
+/// JSON Schema of a repository's `forest.toml`
+pub fn repo_config() -> String {
+    to_json(schema_for!(RootConfig))
+}
+
+fn to_json(schema: schemars::Schema) -> String {
+    let mut json = serde_json::to_string_pretty(&schema).expect("schemas serialize to JSON");
+    json.push('\n');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The shipped schemas are generated: after changing the configuration
+    // structs, update them with `forest config schema [--repo] > schema/...`
+    #[test]
+    fn test_config_schema_is_up_to_date() {
+        assert_eq!(config(), include_str!("../../schema/config.schema.json"));
+    }
+
+    #[test]
+    fn test_repo_config_schema_is_up_to_date() {
+        assert_eq!(repo_config(), include_str!("../../schema/forest.schema.json"));
+    }
+}
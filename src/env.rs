@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use crate::application::Application;
+use crate::config::ResolvedConfig;
+use crate::trees::tree_name_from_path;
+use crate::utils::exec::ExecEnv;
+use crate::utils::git::Git;
+
+/// Variables every command run against a root receives: the root's custom
+/// `env` table (from its `resolved` settings) plus forest's own `FOREST_*` context
+pub fn root_env(application: &Application, resolved: &ResolvedConfig, root: &str, root_path: &Path) -> ExecEnv {
+    let mut env = resolved.env.clone();
+
+    env.insert("FOREST_ROOT".to_string(), root.to_string());
+    env.insert("FOREST_ROOT_PATH".to_string(), root_path.to_string_lossy().to_string());
+    env.insert("FOREST_BASE_DIR".to_string(), application.base_dir.to_string_lossy().to_string());
+    if let Some(profile) = &application.profile {
+        env.insert("FOREST_PROFILE".to_string(), profile.clone());
+    }
+
+    if let Ok(default_branch) = Git::new(&root_path.to_path_buf()).default_branch() {
+        env.insert("FOREST_DEFAULT_BRANCH".to_string(), default_branch);
+    }
+
+    env
+}
+
+/// Same as `root_env`, plus the tree's `FOREST_TREE`, `FOREST_TREE_PATH` and `FOREST_BRANCH`
+pub fn tree_env(
+    application: &Application,
+    resolved: &ResolvedConfig,
+    root: &str,
+    root_path: &Path,
+    branch: &str,
+    tree_path: &Path,
+) -> ExecEnv {
+    let mut env = root_env(application, resolved, root, root_path);
+
+    env.insert("FOREST_TREE".to_string(), tree_name_from_path(&application.trees_dir, tree_path));
+    env.insert("FOREST_TREE_PATH".to_string(), tree_path.to_string_lossy().to_string());
+    env.insert("FOREST_BRANCH".to_string(), branch.to_string());
+
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::test_application;
+    use crate::config::RootConfig;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[test]
+    fn test_tree_env() {
+        let mut roots = HashMap::new();
+        roots.insert("api".to_string(), RootConfig {
+            env: BTreeMap::from([
+                ("DATABASE_URL".to_string(), "postgres://localhost/api".to_string()),
+                ("FOREST_ROOT".to_string(), "overridden".to_string()),
+            ]),
+            ..Default::default()
+        });
+        let application = test_application(vec![], vec![], roots);
+        let root_path = application.roots_dir.join("api");
+        let tree_path = application.trees_dir.join("api--feat--x");
+
+        let resolved = application.resolved_config("api").unwrap();
+
+        let env = tree_env(&application, &resolved, "api", &root_path, "feat/x", &tree_path);
+
+        assert_eq!(env["FOREST_ROOT"], "api");
+        assert_eq!(env["FOREST_ROOT_PATH"], root_path.to_string_lossy());
+        assert_eq!(env["FOREST_TREE"], "api--feat--x");
+        assert_eq!(env["FOREST_TREE_PATH"], tree_path.to_string_lossy());
+        assert_eq!(env["FOREST_BRANCH"], "feat/x");
+        assert_eq!(env["FOREST_BASE_DIR"], application.base_dir.to_string_lossy());
+        assert_eq!(env["DATABASE_URL"], "postgres://localhost/api");
+        assert!(!env.contains_key("FOREST_DEFAULT_BRANCH"));
+    }
+
+    #[test]
+    fn test_root_env_without_tree() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        let root_path = application.roots_dir.join("api");
+
+        let resolved = application.resolved_config("api").unwrap();
+
+        let env = root_env(&application, &resolved, "api", &root_path);
+
+        assert_eq!(env["FOREST_ROOT"], "api");
+        assert!(!env.contains_key("FOREST_TREE"));
+        assert!(!env.contains_key("FOREST_BRANCH"));
+    }
+}
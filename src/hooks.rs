@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::application::Application;
+use crate::config::{ExecCommand, Hooks, OnError, ResolvedConfig};
+use crate::trees::setup::exec_commands;
+use crate::trees::steps;
+use crate::utils::cli_ui;
+use crate::utils::exec::ExecEnv;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+    PreCreate,
+    PostCreate,
+    PreDelete,
+    PostDelete,
+    PostClone,
+}
+
+impl Hook {
+    fn name(&self) -> &'static str {
+        match self {
+            Hook::PreCreate => "pre_create",
+            Hook::PostCreate => "post_create",
+            Hook::PreDelete => "pre_delete",
+            Hook::PostDelete => "post_delete",
+            Hook::PostClone => "post_clone",
+        }
+    }
+
+    fn commands<'a>(&self, hooks: &'a Hooks) -> &'a Vec<ExecCommand> {
+        match self {
+            Hook::PreCreate => &hooks.pre_create,
+            Hook::PostCreate => &hooks.post_create,
+            Hook::PreDelete => &hooks.pre_delete,
+            Hook::PostDelete => &hooks.post_delete,
+            Hook::PostClone => &hooks.post_clone,
+        }
+    }
+
+    fn vetoes(&self) -> bool {
+        matches!(self, Hook::PreCreate | Hook::PreDelete)
+    }
+}
+
+/// Runs the commands configured for `hook` in `dir`
+pub fn run(
+    application: &Application,
+    resolved: &ResolvedConfig,
+    hook: Hook,
+    root: &str,
+    dir: &Path,
+    env: &ExecEnv,
+) -> Result<()> {
+    let commands = hook.commands(&resolved.hooks);
+
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let start = format!("Running {} hooks...", hook.name());
+    println!("{}", cli_ui::context(&start));
+
+    // Hooks have no background to run in, unlike `exec`
+    steps::validate(commands, false).with_context(|| format!("Invalid {} hook", hook.name()))?;
+
+    let on_error = if hook.vetoes() { OnError::Abort } else { resolved.on_error };
+
+    let repo_root = application.roots_dir.join(root);
+
+    exec_commands(dir, &repo_root, commands, on_error, env)
+        .with_context(|| format!("{} hook failed", hook.name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::test_application;
+    use crate::config::RootConfig;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn application_with_hooks(hooks: Hooks) -> crate::application::TestApplication {
+        let mut roots = HashMap::new();
+        roots.insert("test-repo".to_string(), RootConfig { hooks, ..Default::default() });
+        test_application(vec![], vec![], roots)
+    }
+
+    #[test]
+    fn test_run_without_hooks() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        let dir = TempDir::new().unwrap();
+
+        let result = run(&application, &application.resolved_config("test-repo").unwrap(), Hook::PreCreate, "test-repo", dir.path(), &ExecEnv::new());
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_run_pre_hook_vetoes() {
+        let application = application_with_hooks(Hooks {
+            pre_delete: vec!["exit 1".into(), "echo 'never runs' > skipped.txt".into()],
+            ..Default::default()
+        });
+        let dir = TempDir::new().unwrap();
+
+        let err = run(&application, &application.resolved_config("test-repo").unwrap(), Hook::PreDelete, "test-repo", dir.path(), &ExecEnv::new())
+            .unwrap_err();
+
+        assert!(err.to_string().contains("pre_delete hook failed"));
+        assert!(!dir.path().join("skipped.txt").exists());
+    }
+
+    #[test]
+    fn test_run_post_hook_follows_on_error() {
+        let application = application_with_hooks(Hooks {
+            post_delete: vec!["exit 1".into(), "echo $HOOK_VAR > ran.txt".into()],
+            ..Default::default()
+        });
+        let dir = TempDir::new().unwrap();
+        let env = ExecEnv::from([("HOOK_VAR".to_string(), "set".to_string())]);
+
+        let result = run(&application, &application.resolved_config("test-repo").unwrap(), Hook::PostDelete, "test-repo", dir.path(), &env);
+
+        assert!(result.is_ok());
+        assert_eq!(fs::read_to_string(dir.path().join("ran.txt")).unwrap().trim(), "set");
+    }
+    #[test]
+    fn test_run_rejects_background_commands() {
+        let application = application_with_hooks(Hooks {
+            post_create: vec![ExecCommand { background: true, ..ExecCommand::from("touch ran.txt") }],
+            ..Default::default()
+        });
+        let dir = TempDir::new().unwrap();
+
+        let err = run(&application, &application.resolved_config("test-repo").unwrap(), Hook::PostCreate, "test-repo", dir.path(), &ExecEnv::new())
+            .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("Invalid post_create hook: Step 'step 1' can't run in the background here"));
+        assert!(!dir.path().join("ran.txt").exists());
+    }
+}
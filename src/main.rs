@@ -2,21 +2,34 @@ mod application;
 mod trees;
 mod roots;
 mod config;
+mod env;
+mod hooks;
+mod trust;
 
 mod utils {
     pub mod path;
     pub mod git;
     pub mod exec;
     pub mod cli_ui;
+    pub mod template;
 }
 
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 #[command(name = "forest")]
 #[command(about = "A CLI tool to make working with easier by establishing a few conventions and abstracting away some git commands.", long_about = None)]
 #[command(version)]
 struct Cli {
+    /// Configuration file to use instead of the default one (also: FOREST_CONFIG)
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Profile to use, instead of the one whose base_dir contains the current directory (also: FOREST_PROFILE)
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -30,6 +43,72 @@ enum Commands {
     /// Manage worktrees in trees/
     #[command(subcommand)]
     Trees(TreesCommands),
+
+    /// Inspect and change forest's configuration file
+    #[command(subcommand)]
+    Config(ConfigCommands),
+
+    /// Manage repositories whose forest.toml you trusted
+    #[command(subcommand)]
+    Trust(TrustCommands),
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommands {
+    /// Show where the configuration file is
+    Path,
+
+    /// Print the configuration file
+    Show {
+        /// Print the configuration forest uses, defaults included
+        #[arg(long)]
+        resolved: bool,
+        /// With --resolved, print the settings a root's trees are set up with
+        #[arg(long, requires = "resolved")]
+        root: Option<String>,
+    },
+
+    /// Print the value of a setting, e.g. `general.base_dir`
+    #[command(arg_required_else_help = true)]
+    Get {
+        /// Dotted key, e.g. `roots.my-repo.copy`
+        key: String,
+    },
+
+    /// Change a setting, keeping the rest of the file as is
+    #[command(arg_required_else_help = true)]
+    Set {
+        /// Dotted key, e.g. `general.on_error`
+        key: String,
+        /// TOML value (`true`, `3`, `[".env"]`...) or plain text
+        value: String,
+    },
+
+    /// Open the configuration file in $VISUAL/$EDITOR, then check it
+    Edit,
+
+    /// Check the configuration file for errors and unknown keys
+    Check,
+
+    /// Print the JSON Schema of config.toml, for editors to validate and autocomplete it
+    Schema {
+        /// Print the schema of a repository's forest.toml instead
+        #[arg(long)]
+        repo: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TrustCommands {
+    /// List trusted repository configurations
+    List,
+
+    /// Stop trusting a root's forest.toml, so forest asks again before using it
+    #[command(arg_required_else_help = true)]
+    Revoke {
+        /// Root name (same as repo)
+        root: String,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -51,6 +130,14 @@ enum RootsCommands {
         root: String,
     },
 
+    /// Show the configuration trees of a root are set up with,
+    /// after merging general and root settings
+    #[command(arg_required_else_help = true)]
+    Config {
+        /// Root name (same as repo)
+        root: String,
+    },
+
     /// Execute a command against a root.
     /// Similar to entering root dir and inputting <command>
     #[command(arg_required_else_help = true)]
@@ -69,8 +156,12 @@ enum TreesCommands {
     Create {
         /// Root name (same as repo)
         root: String,
-        /// Name for your new branch. Follow your git conventions
+        /// Name for your new branch. Follow your git conventions, or write e.g.
+        /// "PROJ-12 fix login race" when branch_template is set
         new_branch_name: String,
+        /// Use new_branch_name as is, ignoring branch_template
+        #[arg(long)]
+        exact: bool,
     },
 
     /// List all worktrees
@@ -80,6 +171,18 @@ enum TreesCommands {
         root: Option<String>,
     },
 
+    /// Show the output of a worktree's background setup commands
+    #[command(arg_required_else_help = true)]
+    Logs {
+        /// Filter by root (repository name)
+        root: String,
+        /// Tree name (same as branch name)
+        tree: String,
+        /// Keep printing new output until the background setup finishes
+        #[arg(long, short)]
+        follow: bool,
+    },
+
     /// Path to a worktree directory
     #[command(arg_required_else_help = true)]
     Path {
@@ -116,28 +219,100 @@ enum TreesCommands {
         /// Tree name (same as branch name)
         tree: String,
     },
+
+    /// List ports allocated to worktrees
+    Ports {
+        /// Filter by root (repository name)
+        #[arg(long)]
+        root: Option<String>,
+    },
+
+    /// Re-apply copy and exec configuration to existing worktrees
+    #[command(arg_required_else_help = true)]
+    Setup {
+        /// Root name (same as repo)
+        root: String,
+        /// Tree name (same as branch name)
+        #[arg(required_unless_present = "all")]
+        tree: Option<String>,
+        /// Set up every tree of the root
+        #[arg(long, conflicts_with = "tree")]
+        all: bool,
+        /// Only copy files
+        #[arg(long, conflicts_with = "exec_only")]
+        copy_only: bool,
+        /// Only execute commands
+        #[arg(long)]
+        exec_only: bool,
+    },
 }
 
 fn main() {
     let args = Cli::parse();
-    let forest = application::Application::new();
+
+    // Config commands must keep working when the configuration is broken
+    let command = match args.command {
+        Commands::Config(config_cmd) => return config_command(config_cmd, args.config, args.profile),
+        command => command,
+    };
+
+    let forest = load_application(args.config, args.profile);
 
     forest.setup();
 
-    match args.command {
+    match command {
         Commands::Roots(roots_cmd) => match roots_cmd {
             RootsCommands::Clone { repository_address } => forest.roots_clone(repository_address),
+            RootsCommands::Config { root } => forest.roots_config(root),
             RootsCommands::Exec { root, command } => forest.roots_exec(root, command),
             RootsCommands::List => forest.roots_list(),
             RootsCommands::Path { root } => forest.roots_path(root),
         },
         Commands::Trees(trees_cmd) => match trees_cmd {
             TreesCommands::Clean { root } => forest.trees_clean(root),
-            TreesCommands::Create { root, new_branch_name } => forest.trees_create(root, new_branch_name),
+            TreesCommands::Create { root, new_branch_name, exact } => forest.trees_create(root, new_branch_name, exact),
             TreesCommands::Delete { root, tree } => forest.trees_delete(root, tree),
             TreesCommands::Exec { root, tree, command } => forest.trees_exec(root, tree, command),
             TreesCommands::List { root } => forest.trees_list(root),
+            TreesCommands::Logs { root, tree, follow } => forest.trees_logs(root, tree, follow),
             TreesCommands::Path { tree, root } => forest.trees_path(root, tree),
+            TreesCommands::Ports { root } => forest.trees_ports(root),
+            TreesCommands::Setup { root, tree, all: _, copy_only, exec_only } => {
+                forest.trees_setup(root, tree, copy_only, exec_only)
+            },
         },
+        Commands::Trust(trust_cmd) => match trust_cmd {
+            TrustCommands::List => forest.trust_list(),
+            TrustCommands::Revoke { root } => forest.trust_revoke(root),
+        },
+        Commands::Config(_) => unreachable!("config commands run without loading the configuration"),
+    }
+}
+
+fn config_command(config_cmd: ConfigCommands, config_flag: Option<PathBuf>, profile_flag: Option<String>) {
+    let config_file = application::config_file(config_flag.clone());
+
+    match config_cmd {
+        ConfigCommands::Path => println!("{}", config_file.display()),
+        ConfigCommands::Show { resolved: false, .. } => application::config_show(&config_file),
+        ConfigCommands::Show { resolved: true, root: None } => application::config_show_effective(&config_file, profile_flag),
+        ConfigCommands::Show { resolved: true, root: Some(root) } => load_application(config_flag, profile_flag).roots_config(root),
+        ConfigCommands::Get { key } => application::config_get(&config_file, profile_flag, &key),
+        ConfigCommands::Set { key, value } => application::config_set(&config_file, &key, &value),
+        ConfigCommands::Edit => application::config_edit(&config_file),
+        ConfigCommands::Check => application::config_check(&config_file),
+        ConfigCommands::Schema { repo: false } => print!("{}", config::schema::config()),
+        ConfigCommands::Schema { repo: true } => print!("{}", config::schema::repo_config()),
+    }
+}
+
+fn load_application(config_flag: Option<PathBuf>, profile_flag: Option<String>) -> application::Application {
+    match application::Application::new(config_flag, profile_flag) {
+        Ok(forest) => forest,
+        Err(err) => {
+            let msg = format!("Error: {:#}", err);
+            eprintln!("\n{}", utils::cli_ui::error(&msg));
+            std::process::exit(1);
+        }
     }
 }
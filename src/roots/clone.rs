@@ -1,18 +1,24 @@
-use std::path::PathBuf;
 use anyhow::{Result, Context};
 
 use super::Root;
 
+use crate::application::Application;
+use crate::env::root_env;
+use crate::hooks::{self, Hook};
 use crate::utils::git::Git;
 
 // TODO: Handle github:org/repo
-pub fn call(roots_dir: &PathBuf, repository_address: String) -> Result<Root> {
+pub fn call(application: &Application, repository_address: String) -> Result<Root> {
     let gitless_repo_address = repository_address.replace(".git", "");
     let repo_name = gitless_repo_address.split('/').last().context("Invalid repository URL")?;
-    let repo_dir = roots_dir.join(repo_name);
+    let repo_dir = application.roots_dir.join(repo_name);
 
     Git::new(&repo_dir).clone(&repository_address)?;
 
+    let resolved = application.resolved_config(repo_name)?;
+    let env = root_env(application, &resolved, repo_name, &repo_dir);
+    hooks::run(application, &resolved, Hook::PostClone, repo_name, &repo_dir, &env)?;
+
     Ok(Root {
         name: repo_name.to_string(),
         path: repo_dir,
@@ -22,17 +28,19 @@ pub fn call(roots_dir: &PathBuf, repository_address: String) -> Result<Root> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::TempDir;
+    use crate::application::{init_test_repo, test_application};
+    use crate::config::{Hooks, RootConfig};
+    use std::collections::HashMap;
 
     const REPO_ADDRESS: &str = "https://github.com/tcione/test-repo.git";
 
     #[test]
     fn test_clone_repo() {
-        let roots_dir = tempfile::TempDir::new().unwrap();
-        let cloned_path = roots_dir.path().join("test-repo");
+        let application = test_application(vec![], vec![], HashMap::new());
+        let cloned_path = application.roots_dir.join("test-repo");
         let git_path = cloned_path.join(".git");
 
-        let result = call(&roots_dir.path().to_path_buf(), REPO_ADDRESS.to_string()).unwrap();
+        let result = call(&application, REPO_ADDRESS.to_string()).unwrap();
 
         assert!(cloned_path.exists());
         assert!(git_path.exists());
@@ -42,10 +50,32 @@ mod tests {
 
     #[test]
     fn test_clone_with_invalid_url() {
-        let roots_dir = TempDir::new().unwrap();
-        let result = call(&roots_dir.path().to_path_buf(), "invalid-url".to_string());
+        let application = test_application(vec![], vec![], HashMap::new());
+        let result = call(&application, "invalid-url".to_string());
         let err = result.unwrap_err();
 
         assert!(err.to_string().contains("repository 'invalid-url' does not exist"))
     }
+
+    #[test]
+    fn test_clone_runs_post_clone_hook() {
+        let mut roots = HashMap::new();
+        roots.insert("origin-repo".to_string(), RootConfig {
+            hooks: Hooks {
+                post_clone: vec!["echo $FOREST_ROOT > cloned.txt".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let application = test_application(vec![], vec![], roots);
+        let origin = application.state_dir.join("origin-repo");
+        init_test_repo(&origin);
+
+        let result = call(&application, origin.to_string_lossy().to_string()).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(result.path.join("cloned.txt")).unwrap().trim(),
+            "origin-repo"
+        );
+    }
 }
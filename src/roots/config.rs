@@ -0,0 +1,34 @@
+use anyhow::{Context, Result};
+
+use crate::application::Application;
+
+/// The settings trees of `root` are set up with, as TOML
+pub fn call(application: &Application, root: &str) -> Result<String> {
+    let resolved = application.resolved_config(root)?;
+
+    toml::to_string(&resolved).with_context(|| format!("Failed to render configuration of root '{}'", root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::test_application;
+    use crate::config::RootConfig;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_config_merges_general_and_root() {
+        let mut roots = HashMap::new();
+        roots.insert("api".to_string(), RootConfig {
+            copy: vec!["config/master.key".into()],
+            ..Default::default()
+        });
+        let application = test_application(vec![".env".to_string()], vec![], roots);
+
+        let output = call(&application, "api").unwrap();
+
+        assert!(output.contains("path = \".env\""));
+        assert!(output.contains("path = \"config/master.key\""));
+        assert!(output.contains("on_error = \"continue\""));
+    }
+}
@@ -1,28 +1,32 @@
 use anyhow::Result;
-use std::path::PathBuf;
-use crate::utils::exec::{ExecError, call as exec_call};
+use crate::application::Application;
+use crate::env::root_env;
+use crate::utils::exec::call as exec_call;
 
-pub fn call(roots_dir: &PathBuf, root: String, command: String) -> Result<(), ExecError> {
-    let root_dir = roots_dir.join(&root);
-    exec_call(&root_dir, &command)
+pub fn call(application: &Application, root: String, command: String) -> Result<()> {
+    let root_dir = application.roots_dir.join(&root);
+    let env = root_env(application, &application.resolved_config(&root)?, &root, &root_dir);
+    exec_call(&root_dir, &command, &env)?;
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::application::test_application;
+    use std::collections::HashMap;
     use std::fs::{create_dir_all, read_to_string};
-    use tempfile::TempDir;
 
     #[test]
     fn test_calls_exec_with_correct_directory() {
-        let roots_tmp_dir = TempDir::new().unwrap();
-        let roots_dir = roots_tmp_dir.path().to_path_buf();
-        let root_dir = roots_dir.join("test-repo").to_path_buf();
+        let application = test_application(vec![], vec![], HashMap::new());
+        let root_dir = application.roots_dir.join("test-repo").to_path_buf();
 
         create_dir_all(&root_dir).unwrap();
 
         call(
-            &roots_dir,
+            &application,
             "test-repo".to_string(),
             "echo testing > testfile.txt".to_string(),
         )
@@ -34,4 +38,24 @@ mod tests {
             "testing\n"
         );
     }
+
+    #[test]
+    fn test_exposes_forest_env() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        let root_dir = application.roots_dir.join("test-repo");
+
+        create_dir_all(&root_dir).unwrap();
+
+        call(
+            &application,
+            "test-repo".to_string(),
+            "echo $FOREST_ROOT $FOREST_ROOT_PATH > env.txt".to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_to_string(root_dir.join("env.txt")).unwrap().trim(),
+            format!("test-repo {}", root_dir.display())
+        );
+    }
 }
@@ -9,6 +9,7 @@ pub struct Root {
 }
 
 pub mod clone;
+pub mod config;
 pub mod get;
 pub mod exec;
 pub mod list;
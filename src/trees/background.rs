@@ -0,0 +1,194 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ExecCommand, OnError};
+use crate::utils::exec::{ExecEnv, spawn_detached};
+
+/// Per-tree directory where forest keeps background setup state
+const STATE_DIR: &str = ".forest";
+const LOG_FILE: &str = "setup.log";
+const STATUS_FILE: &str = "setup.status";
+const PID_FILE: &str = "setup.pid";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetupStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl SetupStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SetupStatus::Running => "running",
+            SetupStatus::Succeeded => "succeeded",
+            SetupStatus::Failed => "failed",
+        }
+    }
+}
+
+pub fn log_path(tree_path: &Path) -> PathBuf {
+    tree_path.join(STATE_DIR).join(LOG_FILE)
+}
+
+/// Runs `commands` one after the other in a detached process, logging their
+/// output under the tree. Commands that would prompt on failure abort instead
+pub fn start(tree_path: &Path, commands: &[&ExecCommand], on_error: OnError, env: &ExecEnv) -> Result<()> {
+    let state_dir = tree_path.join(STATE_DIR);
+    std::fs::create_dir_all(&state_dir)?;
+    // Keeps the state directory out of `git status`
+    std::fs::write(state_dir.join(".gitignore"), "*\n")?;
+    // A previous run's pid must not vouch for this one
+    let _ = std::fs::remove_file(state_dir.join(PID_FILE));
+    std::fs::write(state_dir.join(STATUS_FILE), "running\n")?;
+
+    let script = script(commands, on_error);
+    let pid = match spawn_detached(&tree_path.to_path_buf(), &script, env, &log_path(tree_path)) {
+        Ok(pid) => pid,
+        Err(err) => {
+            std::fs::write(state_dir.join(STATUS_FILE), "failed\n")?;
+            return Err(err.into());
+        }
+    };
+    std::fs::write(state_dir.join(PID_FILE), format!("{}\n", pid))?;
+
+    Ok(())
+}
+
+/// Status of the latest background setup, if the tree ever had one
+pub fn status(tree_path: &Path) -> Option<SetupStatus> {
+    let state_dir = tree_path.join(STATE_DIR);
+    let raw = std::fs::read_to_string(state_dir.join(STATUS_FILE)).ok()?;
+
+    match raw.trim() {
+        "succeeded" => Some(SetupStatus::Succeeded),
+        "running" if is_alive(&state_dir) => Some(SetupStatus::Running),
+        _ => Some(SetupStatus::Failed),
+    }
+}
+
+fn is_alive(state_dir: &Path) -> bool {
+    // `start` writes the pid once the process is spawned, so without one it never ran
+    let Ok(pid) = std::fs::read_to_string(state_dir.join(PID_FILE)) else {
+        return false;
+    };
+
+    std::process::Command::new("kill")
+        .args(["-0", pid.trim()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+fn script(commands: &[&ExecCommand], on_error: OnError) -> String {
+    let status_file = format!("{}/{}", STATE_DIR, STATUS_FILE);
+    let mut lines = vec!["status=succeeded".to_string()];
+
+    for command in commands {
+        let on_failure = match command.on_error.unwrap_or(on_error) {
+            OnError::Continue => "status=failed".to_string(),
+            OnError::Abort | OnError::Prompt => {
+                format!("echo failed > {}; exit $code", status_file)
+            }
+        };
+
+        lines.push(format!("printf '%s\\n' {}", quote(&format!(">> command: {}", command.run))));
+        match &command.cwd {
+            Some(cwd) => lines.push(format!("(cd {} && sh -c {})", quote(cwd), quote(&command.run))),
+            None => lines.push(format!("sh -c {}", quote(&command.run))),
+        }
+        lines.push("code=$?".to_string());
+        lines.push(format!(
+            "if [ $code -ne 0 ]; then echo \"<< command error (exit code $code)\"; {}; else echo '<< command done'; fi",
+            on_failure
+        ));
+    }
+
+    lines.push(format!("echo $status > {}", status_file));
+    lines.join("\n")
+}
+
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    fn wait_for_completion(tree_path: &Path) -> Option<SetupStatus> {
+        let started = Instant::now();
+        while status(tree_path) == Some(SetupStatus::Running) && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        status(tree_path)
+    }
+
+    #[test]
+    fn test_status_without_background_setup() {
+        let tree = TempDir::new().unwrap();
+
+        assert_eq!(status(tree.path()), None);
+    }
+
+    #[test]
+    fn test_start_succeeds() {
+        let tree = TempDir::new().unwrap();
+        let first: ExecCommand = "echo \"it's $GREETING\"".into();
+        let second = ExecCommand { cwd: Some("web".to_string()), ..ExecCommand::from("echo done > done.txt") };
+        fs::create_dir(tree.path().join("web")).unwrap();
+        let env = ExecEnv::from([("GREETING".to_string(), "alive".to_string())]);
+
+        start(tree.path(), &[&first, &second], OnError::Abort, &env).unwrap();
+
+        assert_eq!(wait_for_completion(tree.path()), Some(SetupStatus::Succeeded));
+        assert!(tree.path().join("web").join("done.txt").exists());
+        let log = fs::read_to_string(log_path(tree.path())).unwrap();
+        assert!(log.contains(">> command: echo \"it's $GREETING\""));
+        assert!(log.contains("it's alive"));
+        assert_eq!(fs::read_to_string(tree.path().join(".forest").join(".gitignore")).unwrap(), "*\n");
+    }
+
+    #[test]
+    fn test_start_aborts_on_failure() {
+        let tree = TempDir::new().unwrap();
+        let failing: ExecCommand = "exit 4".into();
+        let skipped: ExecCommand = "echo never > skipped.txt".into();
+
+        start(tree.path(), &[&failing, &skipped], OnError::Abort, &ExecEnv::new()).unwrap();
+
+        assert_eq!(wait_for_completion(tree.path()), Some(SetupStatus::Failed));
+        assert!(!tree.path().join("skipped.txt").exists());
+        assert!(fs::read_to_string(log_path(tree.path())).unwrap().contains("exit code 4"));
+    }
+
+    #[test]
+    fn test_start_fails_to_spawn() {
+        let tree = TempDir::new().unwrap();
+        // The log can't be created where a directory is in the way
+        fs::create_dir_all(log_path(tree.path())).unwrap();
+        fs::write(tree.path().join(".forest").join(PID_FILE), format!("{}\n", std::process::id())).unwrap();
+        let command: ExecCommand = "echo never".into();
+
+        let result = start(tree.path(), &[&command], OnError::Abort, &ExecEnv::new());
+
+        assert!(result.is_err());
+        assert_eq!(status(tree.path()), Some(SetupStatus::Failed));
+    }
+
+    #[test]
+    fn test_start_continues_on_failure() {
+        let tree = TempDir::new().unwrap();
+        let failing: ExecCommand = "exit 4".into();
+        let next: ExecCommand = "echo ran > ran.txt".into();
+
+        start(tree.path(), &[&failing, &next], OnError::Continue, &ExecEnv::new()).unwrap();
+
+        assert_eq!(wait_for_completion(tree.path()), Some(SetupStatus::Failed));
+        assert!(tree.path().join("ran.txt").exists());
+    }
+}
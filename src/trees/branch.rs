@@ -0,0 +1,132 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::application::Application;
+use crate::utils::git::Git;
+
+/// Branch to create for what the user typed: `input` itself, or `branch_template`
+/// filled from it unless `exact` is set
+pub fn name(application: &Application, input: &str, exact: bool) -> Result<String> {
+    match &application.config.general.branch_template {
+        Some(template) if !exact => render(template, input, std::env::var("USER").ok().as_deref()),
+        _ => Ok(input.to_string()),
+    }
+}
+
+/// Fills `template` from `input`: `{ticket}` is a leading ticket id such as
+/// `PROJ-12`, `{slug}` the rest of `input` in lowercase-with-dashes and
+/// `{user}` the current user
+fn render(template: &str, input: &str, user: Option<&str>) -> Result<String> {
+    let unknown = template.replace("{user}", "").replace("{ticket}", "").replace("{slug}", "");
+    if unknown.contains(['{', '}']) {
+        anyhow::bail!("branch_template '{}' only accepts {{user}}, {{ticket}} and {{slug}}", template);
+    }
+
+    let ticket_regex = Regex::new(r"^\s*([A-Za-z][A-Za-z0-9]*-[0-9]+)\b(.*)$").unwrap();
+    let (ticket, rest) = match ticket_regex.captures(input) {
+        Some(captures) => (Some(captures[1].to_string()), captures[2].to_string()),
+        None => (None, input.to_string()),
+    };
+
+    let mut branch = template.to_string();
+    if template.contains("{ticket}") {
+        let ticket = ticket.with_context(|| {
+            format!(
+                "'{}' doesn't start with a ticket like PROJ-12, which branch_template '{}' needs (--exact skips it)",
+                input, template
+            )
+        })?;
+        branch = branch.replace("{ticket}", &ticket);
+    }
+    if template.contains("{user}") {
+        let user = user.context("{user} in branch_template needs $USER to be set")?;
+        branch = branch.replace("{user}", &slug(user));
+    }
+    branch = branch.replace("{slug}", &slug(&rest));
+
+    // An empty {slug} shouldn't leave a dangling separator
+    Ok(branch.trim_end_matches(['-', '_', '/']).to_string())
+}
+
+fn slug(text: &str) -> String {
+    let regex = Regex::new(r"[^a-z0-9]+").unwrap();
+
+    regex.replace_all(&text.to_lowercase(), "-").trim_matches('-').to_string()
+}
+
+/// Fails unless git accepts `branch` as a branch name, suggesting one it would
+pub fn validate(branch: &str) -> Result<()> {
+    if Git::is_valid_branch_name(branch)? {
+        return Ok(());
+    }
+
+    let suggestion = suggest(branch);
+    if !suggestion.is_empty() && Git::is_valid_branch_name(&suggestion)? {
+        anyhow::bail!("'{}' is not a valid branch name. Did you mean '{}'?", branch, suggestion);
+    }
+
+    anyhow::bail!("'{}' is not a valid branch name", branch)
+}
+
+/// `branch` without what `git check-ref-format` rejects
+fn suggest(branch: &str) -> String {
+    let forbidden = Regex::new(r"[\s~^:?*\[\\\x00-\x1f\x7f]+|@\{").unwrap();
+    let cleaned = forbidden.replace_all(branch.trim(), "-");
+
+    cleaned
+        .split('/')
+        .map(|component| {
+            let mut component = component.trim_start_matches('.').trim_end_matches(".lock").to_string();
+            while component.contains("..") {
+                component = component.replace("..", ".");
+            }
+            component
+        })
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+        .trim_start_matches('-')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = "{user}/{ticket}-{slug}";
+
+        assert_eq!(render(template, "PROJ-12 fix login race", Some("alice")).unwrap(), "alice/PROJ-12-fix-login-race");
+        assert_eq!(render(template, "  PROJ-12: Fix (login) race!", Some("Alice")).unwrap(), "alice/PROJ-12-fix-login-race");
+        assert_eq!(render(template, "PROJ-12", Some("alice")).unwrap(), "alice/PROJ-12");
+        assert_eq!(render("{slug}", "Update README", None).unwrap(), "update-readme");
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let missing_ticket = render("{ticket}-{slug}", "fix login race", None).unwrap_err();
+        let missing_user = render("{user}/{slug}", "fix", None).unwrap_err();
+        let unknown = render("{team}/{slug}", "fix", None).unwrap_err();
+
+        assert!(missing_ticket.to_string().contains("doesn't start with a ticket like PROJ-12"));
+        assert!(missing_user.to_string().contains("needs $USER"));
+        assert!(unknown.to_string().contains("only accepts {user}, {ticket} and {slug}"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("alice/PROJ-12-fix-login-race").is_ok());
+
+        let err = validate("fix login..race").unwrap_err();
+        assert_eq!(err.to_string(), "'fix login..race' is not a valid branch name. Did you mean 'fix-login.race'?");
+    }
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(suggest("feat/.hidden/x.lock"), "feat/hidden/x");
+        assert_eq!(suggest("-feat//a@{b"), "feat/a-b");
+        assert_eq!(suggest("what?"), "what-");
+    }
+}
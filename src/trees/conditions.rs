@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use crate::config::ExecCommand;
+use crate::utils::exec::ExecEnv;
+
+/// Why `command` should not run, or `None` when all its conditions hold.
+/// Paths are relative to `dir`, the directory the command runs in, and
+/// `if_changed` compares them with the same paths in `repo_root`
+pub fn skip_reason(command: &ExecCommand, repo_root: &Path, dir: &Path, env: &ExecEnv) -> Option<String> {
+    if let Some(path) = &command.if_exists
+        && !dir.join(path).exists()
+    {
+        return Some(format!("'{}' does not exist", path));
+    }
+
+    if let Some(path) = &command.if_changed
+        && !changed(&repo_root.join(path), &dir.join(path))
+    {
+        return Some(format!("'{}' is the same as in the root", path));
+    }
+
+    if let Some(name) = &command.unless_env
+        && is_set(name, env)
+    {
+        return Some(format!("${} is set", name));
+    }
+
+    None
+}
+
+fn changed(root_file: &Path, tree_file: &Path) -> bool {
+    match (std::fs::read(root_file), std::fs::read(tree_file)) {
+        (Ok(root_content), Ok(tree_content)) => root_content != tree_content,
+        (Err(_), Err(_)) => false,
+        _ => true,
+    }
+}
+
+fn is_set(name: &str, env: &ExecEnv) -> bool {
+    match env.get(name) {
+        Some(value) => !value.is_empty(),
+        None => std::env::var(name).is_ok_and(|value| !value.is_empty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn command() -> ExecCommand {
+        ExecCommand::from("bundle install")
+    }
+
+    #[test]
+    fn test_skip_reason_without_conditions() {
+        let root = TempDir::new().unwrap();
+        let tree = TempDir::new().unwrap();
+
+        assert_eq!(skip_reason(&command(), root.path(), tree.path(), &ExecEnv::new()), None);
+    }
+
+    #[test]
+    fn test_skip_reason_if_exists() {
+        let root = TempDir::new().unwrap();
+        let tree = TempDir::new().unwrap();
+        let command = ExecCommand { if_exists: Some("package.json".to_string()), ..command() };
+
+        let missing = skip_reason(&command, root.path(), tree.path(), &ExecEnv::new());
+        fs::write(tree.path().join("package.json"), "{}").unwrap();
+        let present = skip_reason(&command, root.path(), tree.path(), &ExecEnv::new());
+
+        assert_eq!(missing, Some("'package.json' does not exist".to_string()));
+        assert_eq!(present, None);
+    }
+
+    #[test]
+    fn test_skip_reason_if_changed() {
+        let root = TempDir::new().unwrap();
+        let tree = TempDir::new().unwrap();
+        let command = ExecCommand { if_changed: Some("Gemfile.lock".to_string()), ..command() };
+        fs::write(root.path().join("Gemfile.lock"), "rails (7.1)").unwrap();
+        fs::write(tree.path().join("Gemfile.lock"), "rails (7.1)").unwrap();
+
+        let same = skip_reason(&command, root.path(), tree.path(), &ExecEnv::new());
+        fs::write(tree.path().join("Gemfile.lock"), "rails (7.2)").unwrap();
+        let changed = skip_reason(&command, root.path(), tree.path(), &ExecEnv::new());
+        fs::remove_file(root.path().join("Gemfile.lock")).unwrap();
+        let added = skip_reason(&command, root.path(), tree.path(), &ExecEnv::new());
+
+        assert_eq!(same, Some("'Gemfile.lock' is the same as in the root".to_string()));
+        assert_eq!(changed, None);
+        assert_eq!(added, None);
+    }
+
+    #[test]
+    fn test_skip_reason_unless_env() {
+        let root = TempDir::new().unwrap();
+        let tree = TempDir::new().unwrap();
+        let command = ExecCommand { unless_env: Some("FOREST_TEST_CI".to_string()), ..command() };
+
+        let unset = skip_reason(&command, root.path(), tree.path(), &ExecEnv::new());
+        let empty = ExecEnv::from([("FOREST_TEST_CI".to_string(), "".to_string())]);
+        let set = ExecEnv::from([("FOREST_TEST_CI".to_string(), "true".to_string())]);
+
+        assert_eq!(unset, None);
+        assert_eq!(skip_reason(&command, root.path(), tree.path(), &empty), None);
+        assert_eq!(
+            skip_reason(&command, root.path(), tree.path(), &set),
+            Some("$FOREST_TEST_CI is set".to_string())
+        );
+    }
+}
@@ -1,81 +1,137 @@
 use anyhow::Result;
-use regex::Regex;
-use std::path::PathBuf;
-
-use crate::utils::cli_ui;
-use crate::utils::exec::{call as exec_call};
+use regex::{NoExpand, Regex};
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path};
+
+use super::Tree;
+use crate::env::tree_env;
+use crate::hooks::{self, Hook};
+use crate::roots;
+use crate::trees::branch;
+use crate::trees::list::call as list_call;
+use crate::trees::ports;
+use crate::trees::setup::{SetupMode, set_up_worktree};
 use crate::utils::git::Git;
 use crate::application::Application;
+use crate::config::{GeneralConfig, NameNormalization};
+use crate::utils::cli_ui;
 
 pub fn call(application: &Application, root: &str, new_branch_name: &str) -> Result<()> {
+    branch::validate(new_branch_name)?;
+
     let roots_dir = &application.roots_dir;
     let trees_dir = &application.trees_dir;
+    let name = tree_name(&application.config.general, root, new_branch_name)?;
+    let name = available_tree_name(application, root, new_branch_name, name)?;
     let repo_root = roots_dir.join(root);
-    let branch_tree = trees_dir.join(tree_name(root, new_branch_name));
+    let branch_tree = trees_dir.join(&name);
+    let resolved = application.resolved_config(root)?;
+    let mut env = tree_env(application, &resolved, root, &repo_root, new_branch_name, &branch_tree);
+
+    hooks::run(application, &resolved, Hook::PreCreate, root, &repo_root, &env)?;
 
     Git::new(&repo_root).latest_default()?;
-    Git::new(&repo_root).add_worktree(new_branch_name, &branch_tree)?;
 
-    set_up_worktree(application, root, &repo_root, &branch_tree)?;
+    // Allocated before the worktree exists so an exhausted range doesn't leave a half-made tree
+    let ports = ports::allocate(application, root, &name)?;
+    Git::new(&repo_root).add_worktree(new_branch_name, &branch_tree).inspect_err(|_| {
+        let _ = ports::release(application, &name);
+    })?;
 
-    Ok(())
-}
+    set_up_worktree(application, &resolved, root, new_branch_name, &repo_root, &branch_tree, SetupMode::All)?;
 
-fn tree_name(root: &str, new_branch_name: &str) -> String {
-    let trimmed = new_branch_name.trim();
-    let regex = Regex::new(r"[^A-Za-z0-9\-_]+").unwrap();
-    let normalized = regex.replace_all(trimmed, "--");
+    env.extend(ports::env(&ports));
+    hooks::run(application, &resolved, Hook::PostCreate, root, &branch_tree, &env)?;
 
-    format!("{}--{}", root, &normalized)
+    Ok(())
 }
 
-fn set_up_worktree(
-    application: &Application,
-    root: &str,
-    repo_root: &PathBuf,
-    branch_tree: &PathBuf,
-) -> Result<()> {
-    let (copy, exec) = if let Some(root_config) = application.config.roots.get(root) {
-        (&root_config.copy, &root_config.exec)
-    } else {
-        (
-            &application.config.general.copy,
-            &application.config.general.exec,
-        )
-    };
+/// Path of a new tree inside `trees/`, following `tree_name_template`
+pub fn tree_name(general: &GeneralConfig, root: &str, new_branch_name: &str) -> Result<String> {
+    let template = &general.tree_name_template;
 
-    copy_files(repo_root, branch_tree, copy);
-    exec_commands(branch_tree, exec);
+    if template.replace("{root}", "").replace("{branch}", "").contains(['{', '}']) {
+        anyhow::bail!("tree_name_template '{}' only accepts {{root}} and {{branch}}", template);
+    }
 
-    Ok(())
-}
+    let branch = normalize(new_branch_name, &general.tree_name_normalization);
+    let name = template.replace("{root}", root).replace("{branch}", &branch);
 
-fn copy_files(repo_root: &PathBuf, branch_tree: &PathBuf, copy: &Vec<String>) {
-    for file_name in copy {
-        let source = repo_root.join(file_name);
-        let destination = branch_tree.join(file_name);
+    let inside_trees = !name.is_empty() && Path::new(&name).components().all(|c| matches!(c, Component::Normal(_)));
+    if !inside_trees {
+        anyhow::bail!("Tree name '{}' (from tree_name_template '{}') is not a path inside trees/", name, template);
+    }
 
-        let start = format!("Copying '{}' into '{}'...", file_name, branch_tree.to_string_lossy());
-        println!("{}", cli_ui::context(&start));
+    Ok(name)
+}
 
-        if !source.exists() {
-            println!("{}", cli_ui::context_warn("...skipped (does not exist)"));
-            continue;
+/// `name`, unless another branch's tree (of any root) already takes that
+/// directory: then `name` with a suffix derived from the branch, so the
+/// same branch always gets the same directory
+fn available_tree_name(application: &Application, root: &str, branch: &str, name: String) -> Result<String> {
+    // Roots that can't be listed (e.g. a stray non-git directory) have no trees to collide with
+    let trees: Vec<(String, Tree)> = roots::list::call(&application.roots_dir)?
+        .into_iter()
+        .filter_map(|other_root| list_call(application, &Some(other_root.name)).ok())
+        .flatten()
+        .flat_map(|(tree_root, trees)| trees.into_iter().map(move |tree| (tree_root.clone(), tree)))
+        .collect();
+
+    let taken_by = |candidate: &str| {
+        let path = application.trees_dir.join(candidate);
+        let tree = trees
+            .iter()
+            .find(|(_, tree)| tree.path == path || path.starts_with(&tree.path) || tree.path.starts_with(&path));
+
+        match tree {
+            Some((tree_root, tree)) => Some(Occupant::Tree(tree_root, tree)),
+            None if path.exists() => Some(Occupant::Directory),
+            None => None,
         }
+    };
 
-        if let Err(e) = std::fs::copy(&source, &destination) {
-            let end = format!("... failed ({:?})", &e);
-            eprintln!("{}", cli_ui::critical(&end));
-            continue;
-        }
+    let occupant = match taken_by(&name) {
+        None => return Ok(name),
+        // Same branch again: git explains why that can't work
+        Some(Occupant::Tree(tree_root, tree)) if tree_root == root && tree.branch == branch => return Ok(name),
+        Some(occupant) => occupant,
+    };
 
-        println!("{}", cli_ui::context("...copied"));
+    let suffixed = format!("{}-{}", name, &hash(branch)[..6]);
+    if taken_by(&suffixed).is_some() {
+        anyhow::bail!("Tree directory '{}' is taken, and so is '{}'", name, suffixed);
     }
+
+    let msg = match occupant {
+        Occupant::Tree(tree_root, tree) => format!(
+            "'{}' is already the tree of {}'s branch '{}', using '{}'",
+            name, tree_root, tree.branch, suffixed
+        ),
+        Occupant::Directory => format!("'{}' already exists, using '{}'", application.trees_dir.join(&name).display(), suffixed),
+    };
+    println!("{}", cli_ui::context_warn(&msg));
+
+    Ok(suffixed)
+}
+
+enum Occupant<'a> {
+    Tree(&'a str, &'a Tree),
+    Directory,
+}
+
+fn hash(value: &str) -> String {
+    Sha256::digest(value.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
 }
 
-fn exec_commands(branch_tree: &PathBuf, exec: &Vec<String>) {
-    for command in exec {
-        let _ = exec_call(branch_tree, &command);
+/// `branch` as it appears in `{branch}`
+pub fn normalize(branch: &str, normalization: &NameNormalization) -> String {
+    let trimmed = branch.trim();
+    let regex = Regex::new(r"[^A-Za-z0-9\-_]+").unwrap();
+    let normalized = regex.replace_all(trimmed, NoExpand(&normalization.replacement));
+
+    match normalization.lowercase {
+        true => normalized.to_lowercase(),
+        false => normalized.to_string(),
     }
 }
 
@@ -84,10 +140,10 @@ mod tests {
     use super::*;
     use std::collections::HashMap;
     use std::fs;
-    use tempfile::TempDir;
+    use std::path::PathBuf;
     use crate::roots::clone;
-    use crate::application::test_application;
-    use crate::config::RootConfig;
+    use crate::application::{init_test_repo, test_application};
+    use crate::config::{Hooks, PortsConfig, RootConfig};
 
     const TEST_REPO_URL: &str = "https://github.com/tcione/test-repo.git";
 
@@ -119,7 +175,7 @@ mod tests {
         );
         let tree_path = application.trees_dir.join("test-repo--feature--new-feature");
 
-        clone::call(&application.roots_dir, TEST_REPO_URL.to_string()).unwrap();
+        clone::call(&application, TEST_REPO_URL.to_string()).unwrap();
         fs::write(&application.roots_dir.join("test-repo").join(".env"), "VAR=test").unwrap();
 
         call(&application, "test-repo", "feature/new-feature").unwrap();
@@ -143,7 +199,7 @@ mod tests {
     fn test_duplicate_branch_name() {
         let application = test_application(vec![], vec![], HashMap::new());
 
-        clone::call(&application.roots_dir, TEST_REPO_URL.to_string()).unwrap();
+        clone::call(&application, TEST_REPO_URL.to_string()).unwrap();
         call(&application, "test-repo", "feature/new-feature").unwrap();
         let err = call(&application, "test-repo", "feature/new-feature").unwrap_err();
 
@@ -153,194 +209,166 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_create_runs_hooks() {
+        let mut roots = HashMap::new();
+        roots.insert("local-repo".to_string(), RootConfig {
+            hooks: Hooks {
+                pre_create: vec!["echo $FOREST_BRANCH > pre_create.txt".into()],
+                post_create: vec!["echo $FOREST_TREE > post_create.txt".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let application = test_application(vec![], vec![], roots);
+        let root_path = application.roots_dir.join("local-repo");
+        let tree_path = application.trees_dir.join("local-repo--feature");
+
+        init_test_repo(&root_path);
+        call(&application, "local-repo", "feature").unwrap();
+
+        assert_eq!(fs::read_to_string(root_path.join("pre_create.txt")).unwrap().trim(), "feature");
+        assert_eq!(fs::read_to_string(tree_path.join("post_create.txt")).unwrap().trim(), "local-repo--feature");
+    }
+
+    #[test]
+    fn test_create_vetoed_by_pre_create() {
+        let mut roots = HashMap::new();
+        roots.insert("local-repo".to_string(), RootConfig {
+            hooks: Hooks {
+                pre_create: vec!["test \"$FOREST_BRANCH\" != forbidden".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let application = test_application(vec![], vec![], roots);
+        init_test_repo(&application.roots_dir.join("local-repo"));
+
+        let err = call(&application, "local-repo", "forbidden").unwrap_err();
+
+        assert!(err.to_string().contains("pre_create hook failed"));
+        assert!(!application.trees_dir.join("local-repo--forbidden").exists());
+    }
+
+    #[test]
+    fn test_create_with_colliding_tree_name() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        let root_path = application.roots_dir.join("local-repo");
+        init_test_repo(&root_path);
+
+        call(&application, "local-repo", "feat/a-b").unwrap();
+        call(&application, "local-repo", "feat@a-b").unwrap();
+
+        let suffixed = format!("local-repo--feat--a-b-{}", &hash("feat@a-b")[..6]);
+        assert_eq!(tree_branch(&application.trees_dir.join("local-repo--feat--a-b")).unwrap(), "feat/a-b");
+        assert_eq!(tree_branch(&application.trees_dir.join(suffixed)).unwrap(), "feat@a-b");
+    }
+
+    #[test]
+    fn test_create_with_exhausted_port_range() {
+        let mut application = test_application(vec![], vec![], HashMap::new());
+        application.application.config.general.ports = Some(PortsConfig { range: [4000, 4001], per_tree: 2 });
+        init_test_repo(&application.roots_dir.join("local-repo"));
+
+        call(&application, "local-repo", "one").unwrap();
+        let err = call(&application, "local-repo", "two").unwrap_err();
+
+        assert!(err.to_string().contains("No 2 free port(s) left"));
+        assert!(!application.trees_dir.join("local-repo--two").exists());
+    }
+
+    #[test]
+    fn test_create_rejects_invalid_branch_name() {
+        let mut roots = HashMap::new();
+        roots.insert("local-repo".to_string(), RootConfig {
+            hooks: Hooks { pre_create: vec!["touch pre_create.txt".into()], ..Default::default() },
+            ..Default::default()
+        });
+        let application = test_application(vec![], vec![], roots);
+        let root_path = application.roots_dir.join("local-repo");
+        init_test_repo(&root_path);
+
+        let err = call(&application, "local-repo", "fix login").unwrap_err();
+
+        assert!(err.to_string().contains("Did you mean 'fix-login'?"));
+        assert!(!root_path.join("pre_create.txt").exists());
+    }
+
+    #[test]
+    fn test_create_with_non_git_directory_in_roots() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        init_test_repo(&application.roots_dir.join("local-repo"));
+        fs::create_dir_all(application.roots_dir.join("notes")).unwrap();
+
+        call(&application, "local-repo", "feat").unwrap();
+
+        assert!(application.trees_dir.join("local-repo--feat").exists());
+    }
+
     // Unit
     #[test]
     fn test_tree_name() {
+        let general = GeneralConfig::default();
+
         assert_eq!(
-            tree_name("myrepo", "feature/normal"),
+            tree_name(&general, "myrepo", "feature/normal").unwrap(),
             "myrepo--feature--normal"
         );
         assert_eq!(
-            tree_name("myrepo", "hotfix@at-you"),
+            tree_name(&general, "myrepo", "hotfix@at-you").unwrap(),
             "myrepo--hotfix--at-you"
         );
         assert_eq!(
-            tree_name("myrepo", "hotfix/@slash-at-you"),
+            tree_name(&general, "myrepo", "hotfix/@slash-at-you").unwrap(),
             "myrepo--hotfix--slash-at-you"
         );
         assert_eq!(
-            tree_name("myrepo", "feat/user-mgmt_new"),
+            tree_name(&general, "myrepo", "feat/user-mgmt_new").unwrap(),
             "myrepo--feat--user-mgmt_new"
         );
         assert_eq!(
-            tree_name("myrepo", "feat//too-many-hyphens"),
+            tree_name(&general, "myrepo", "feat//too-many-hyphens").unwrap(),
             "myrepo--feat--too-many-hyphens"
         );
         assert_eq!(
-            tree_name("myrepo", "feat/////way-too-many-hyphens"),
+            tree_name(&general, "myrepo", "feat/////way-too-many-hyphens").unwrap(),
             "myrepo--feat--way-too-many-hyphens"
         );
         assert_eq!(
-            tree_name("myrepo", "        feat/trimmed   "),
+            tree_name(&general, "myrepo", "        feat/trimmed   ").unwrap(),
             "myrepo--feat--trimmed"
         );
     }
 
     #[test]
-    fn test_copy_files_with_empty_list() {
-        let repo_root = TempDir::new().unwrap();
-        let branch_tree = TempDir::new().unwrap();
-        let empty_copy_list = vec![];
-
-        copy_files(
-            &repo_root.path().to_path_buf(),
-            &branch_tree.path().to_path_buf(),
-            &empty_copy_list
-        );
-
-        assert_eq!(branch_tree.path().read_dir().unwrap().count(), 0);
-    }
-
-    #[test]
-    fn test_copy_files_filled_list() {
-        let repo_root = TempDir::new().unwrap();
-        let branch_tree = TempDir::new().unwrap();
-
-        fs::write(repo_root.path().join("file1.txt"), "content1").unwrap();
-        fs::write(repo_root.path().join("file2.txt"), "content2").unwrap();
-
-        let copy_list = vec![
-            "file1.txt".to_string(),
-            "nonexistent.txt".to_string(),
-            "file2.txt".to_string()
-        ];
-
-        copy_files(
-            &repo_root.path().to_path_buf(),
-            &branch_tree.path().to_path_buf(),
-            &copy_list
-        );
-
-        assert!(branch_tree.path().join("file1.txt").exists());
-        assert!(branch_tree.path().join("file2.txt").exists());
-        assert_eq!(fs::read_to_string(branch_tree.path().join("file1.txt")).unwrap(), "content1");
-        assert_eq!(fs::read_to_string(branch_tree.path().join("file2.txt")).unwrap(), "content2");
-        assert!(!branch_tree.path().join("nonexistent.txt").exists());
+    fn test_tree_name_with_template() {
+        let general = GeneralConfig {
+            tree_name_template: "{root}/{branch}".to_string(),
+            tree_name_normalization: NameNormalization { replacement: "-".to_string(), lowercase: true },
+            ..Default::default()
+        };
+
+        assert_eq!(tree_name(&general, "api", "Feat/JIRA-12").unwrap(), "api/feat-jira-12");
     }
 
     #[test]
-    fn test_exec_commands_with_empty_list() {
-        let branch_tree = TempDir::new().unwrap();
-        let empty_exec_list = vec![];
-
-        exec_commands(
-            &branch_tree.path().to_path_buf(),
-            &empty_exec_list
-        );
-
-        // Function completes without panicking - that's the test
+    fn test_tree_name_nested_by_branch() {
+        let general = GeneralConfig {
+            tree_name_template: "{branch}".to_string(),
+            tree_name_normalization: NameNormalization { replacement: "/".to_string(), lowercase: false },
+            ..Default::default()
+        };
+
+        assert_eq!(tree_name(&general, "api", "feat/login").unwrap(), "feat/login");
+        assert!(tree_name(&general, "api", "@/login").is_err());
     }
 
     #[test]
-    fn test_exec_commands_with_existing_commands() {
-        let branch_tree = TempDir::new().unwrap();
-        let exec_list = vec![
-            "echo 'test output' > output.txt".to_string(),
-            "ls".to_string()
-        ];
-
-        exec_commands(
-            &branch_tree.path().to_path_buf(),
-            &exec_list
-        );
+    fn test_tree_name_unknown_placeholder() {
+        let general = GeneralConfig { tree_name_template: "{user}-{branch}".to_string(), ..Default::default() };
 
-        assert!(branch_tree.path().join("output.txt").exists());
-        assert_eq!(
-            fs::read_to_string(branch_tree.path().join("output.txt")).unwrap().trim(),
-            "test output"
-        );
-    }
+        let err = tree_name(&general, "api", "feat").unwrap_err();
 
-    #[test]
-    fn test_exec_commands_with_non_existing_commands() {
-        let branch_tree = TempDir::new().unwrap();
-        let exec_list = vec![
-            "nonexistent_command".to_string(),
-            "echo 'still works' > success.txt".to_string()
-        ];
-
-        exec_commands(
-            &branch_tree.path().to_path_buf(),
-            &exec_list
-        );
-
-        assert!(branch_tree.path().join("success.txt").exists());
-        assert_eq!(
-            fs::read_to_string(branch_tree.path().join("success.txt")).unwrap().trim(),
-            "still works"
-        );
-    }
-
-    #[test]
-    fn test_set_up_worktree_no_root_config() {
-        let application = test_application(
-            vec!["general_file.txt".to_string()],
-            vec!["echo 'general command' > general_output.txt".to_string()],
-            HashMap::new()
-        );
-        let repo_root = TempDir::new().unwrap();
-        let branch_tree = TempDir::new().unwrap();
-
-        fs::write(repo_root.path().join("general_file.txt"), "general content").unwrap();
-
-        set_up_worktree(
-            &application,
-            "test-repo",
-            &repo_root.path().to_path_buf(),
-            &branch_tree.path().to_path_buf()
-        ).unwrap();
-
-        assert!(branch_tree.path().join("general_file.txt").exists());
-        assert_eq!(
-            fs::read_to_string(branch_tree.path().join("general_file.txt")).unwrap(),
-            "general content"
-        );
-        assert!(branch_tree.path().join("general_output.txt").exists());
-        assert_eq!(
-            fs::read_to_string(branch_tree.path().join("general_output.txt")).unwrap().trim(),
-            "general command"
-        );
-    }
-
-    #[test]
-    fn test_set_up_worktree_with_root_config() {
-        let mut root_configs = HashMap::new();
-        root_configs.insert("test-repo".to_string(), RootConfig {
-            copy: vec!["root_file.txt".to_string()],
-            exec: vec!["echo 'root command' > root_output.txt".to_string()],
-        });
-
-        let application = test_application(
-            vec!["general_file.txt".to_string()],
-            vec!["echo 'general command' > general_output.txt".to_string()],
-            root_configs
-        );
-        let repo_root = TempDir::new().unwrap();
-        let branch_tree = TempDir::new().unwrap();
-
-        fs::write(repo_root.path().join("general_file.txt"), "general content").unwrap();
-        fs::write(repo_root.path().join("root_file.txt"), "root content").unwrap();
-
-        set_up_worktree(
-            &application,
-            "test-repo",
-            &repo_root.path().to_path_buf(),
-            &branch_tree.path().to_path_buf()
-        ).unwrap();
-
-        assert!(branch_tree.path().join("root_file.txt").exists());
-        assert!(!branch_tree.path().join("general_file.txt").exists());
-        assert!(branch_tree.path().join("root_output.txt").exists());
-        assert!(!branch_tree.path().join("general_output.txt").exists());
+        assert!(err.to_string().contains("only accepts {root} and {branch}"));
     }
 }
@@ -1,6 +1,9 @@
 use crate::application::Application;
+use crate::env::tree_env;
+use crate::hooks::{self, Hook};
 use crate::roots::get::call as root_get_call;
 use crate::trees::get::call as get_call;
+use crate::trees::{ports, remove_empty_parents};
 use crate::utils::git::Git;
 use anyhow::{Context, Result};
 
@@ -10,20 +13,33 @@ pub fn call(application: &Application, root: &str, tree: &str) -> Result<()> {
     let tree_obj = get_call(application, root, tree)
         .with_context(|| format!("Failed to find tree '{}' in root '{}'", tree, root))?;
     let git = Git::new(&root_obj.path);
+    let resolved = application.resolved_config(root)?;
+    let mut env = tree_env(application, &resolved, root, &root_obj.path, &tree_obj.branch, &tree_obj.path);
+    env.extend(ports::env(&ports::find(application, &tree_obj.name)?));
+
+    hooks::run(application, &resolved, Hook::PreDelete, root, &tree_obj.path, &env)?;
 
     git.remove_worktree(&tree_obj.path)
         .with_context(|| format!("Failed to remove worktree at '{}'", tree_obj.path.display()))?;
+    remove_empty_parents(&application.trees_dir, &tree_obj.path);
+
+    // The tree is gone, so its ports are free even if what follows fails
+    ports::release(application, &tree_obj.name)
+        .with_context(|| format!("Failed to release ports of '{}'", tree_obj.name))?;
 
     git.delete_branch(&tree_obj.branch)
         .with_context(|| format!("Failed to delete branch '{}'", tree_obj.branch))?;
 
+    hooks::run(application, &resolved, Hook::PostDelete, root, &root_obj.path, &env)?;
+
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::application::test_application;
+    use crate::application::{init_test_repo, test_application};
+    use crate::config::{ExecCommand, Hooks, OnError, PortsConfig, RootConfig};
     use crate::roots::clone;
     use crate::trees::create;
     use std::collections::HashMap;
@@ -34,7 +50,7 @@ mod tests {
     fn test_delete_tree_success() {
         let application = test_application(vec![], vec![], HashMap::new());
 
-        clone::call(&application.roots_dir, TEST_REPO_URL.to_string()).unwrap();
+        clone::call(&application, TEST_REPO_URL.to_string()).unwrap();
         create::call(&application, "test-repo", "feature-branch").unwrap();
 
         let tree_path = application.trees_dir.join("test-repo--feature-branch");
@@ -59,11 +75,80 @@ mod tests {
         assert!(!String::from_utf8_lossy(&branch_check.stdout).contains("feature-branch"));
     }
 
+    #[test]
+    fn test_delete_runs_hooks() {
+        let mut roots = HashMap::new();
+        roots.insert("local-repo".to_string(), RootConfig {
+            hooks: Hooks {
+                pre_delete: vec!["echo $FOREST_TREE_PATH > ../pre_delete.txt".into()],
+                post_delete: vec!["echo $FOREST_BRANCH > post_delete.txt".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let application = test_application(vec![], vec![], roots);
+        let root_path = application.roots_dir.join("local-repo");
+        let tree_path = application.trees_dir.join("local-repo--feature");
+
+        init_test_repo(&root_path);
+        create::call(&application, "local-repo", "feature").unwrap();
+        call(&application, "local-repo", "feature").unwrap();
+
+        assert!(!tree_path.exists());
+        assert_eq!(
+            std::fs::read_to_string(application.trees_dir.join("pre_delete.txt")).unwrap().trim(),
+            tree_path.to_string_lossy()
+        );
+        assert_eq!(std::fs::read_to_string(root_path.join("post_delete.txt")).unwrap().trim(), "feature");
+    }
+
+    #[test]
+    fn test_delete_vetoed_by_pre_delete() {
+        let mut roots = HashMap::new();
+        roots.insert("local-repo".to_string(), RootConfig {
+            hooks: Hooks {
+                pre_delete: vec!["exit 1".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let application = test_application(vec![], vec![], roots);
+        let tree_path = application.trees_dir.join("local-repo--feature");
+
+        init_test_repo(&application.roots_dir.join("local-repo"));
+        create::call(&application, "local-repo", "feature").unwrap();
+        let err = call(&application, "local-repo", "feature").unwrap_err();
+
+        assert!(err.to_string().contains("pre_delete hook failed"));
+        assert!(tree_path.exists());
+    }
+
+    #[test]
+    fn test_delete_releases_ports_before_post_delete() {
+        let mut roots = HashMap::new();
+        roots.insert("local-repo".to_string(), RootConfig {
+            hooks: Hooks {
+                post_delete: vec![ExecCommand { on_error: Some(OnError::Abort), ..ExecCommand::from("exit 1") }],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let mut application = test_application(vec![], vec![], roots);
+        application.application.config.general.ports = Some(PortsConfig { range: [4000, 4099], per_tree: 1 });
+
+        init_test_repo(&application.roots_dir.join("local-repo"));
+        create::call(&application, "local-repo", "feature").unwrap();
+        let result = call(&application, "local-repo", "feature");
+
+        assert!(result.is_err());
+        assert!(ports::find(&application, "local-repo--feature").unwrap().is_empty());
+    }
+
     #[test]
     fn test_delete_nonexistent_tree() {
         let application = test_application(vec![], vec![], HashMap::new());
 
-        clone::call(&application.roots_dir, TEST_REPO_URL.to_string()).unwrap();
+        clone::call(&application, TEST_REPO_URL.to_string()).unwrap();
 
         let result = call(
             &application,
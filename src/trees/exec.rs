@@ -1,13 +1,22 @@
 use anyhow::{Context, Result};
 
 use crate::application::Application;
+use crate::env::tree_env;
 use crate::trees::get::call as get_call;
 use crate::utils::exec::call as exec_call;
 
 pub fn call(application: &Application, root: &str, tree: &str, command: String) -> Result<()> {
     let tree = get_call(application, root, tree)?;
-
-    exec_call(&tree.path, &command).with_context(|| {
+    let env = tree_env(
+        application,
+        &application.resolved_config(root)?,
+        root,
+        &application.roots_dir.join(root),
+        &tree.branch,
+        &tree.path,
+    );
+
+    exec_call(&tree.path, &command, &env).with_context(|| {
         format!(
             "Failed to execute '{}' in tree '{}'",
             command,
@@ -31,7 +40,7 @@ mod tests {
     fn test_exec_command_success() {
         let application = test_application(vec![], vec![], HashMap::new());
 
-        clone::call(&application.roots_dir, TEST_REPO_URL.to_string()).unwrap();
+        clone::call(&application, TEST_REPO_URL.to_string()).unwrap();
         create::call(&application, "test-repo", "feature").unwrap();
 
         let tree_dir = application.trees_dir.join("test-repo--feature");
@@ -1,5 +1,6 @@
 use super::Tree;
 use crate::application::Application;
+use crate::trees::create::normalize;
 use crate::trees::list::call as list_call;
 use anyhow::{Context, Result};
 
@@ -10,20 +11,44 @@ pub fn call(application: &Application, root: &str, tree: &str) -> Result<Tree> {
         .get(root)
         .with_context(|| format!("Root '{}' not found", root))?;
 
-    let found_tree = root_trees
-        .iter()
-        .find(|t| t.name == tree || t.branch == tree);
-
-    match found_tree {
-        Some(t) => Ok(t.clone()),
-        None => anyhow::bail!("Tree '{}' not found in root '{}'", tree, root),
+    let normalization = &application.config.general.tree_name_normalization;
+    let normalized = normalize(tree, normalization);
+
+    // Most specific first: a branch or a tree name points at a single tree,
+    // while several branches may normalize to the same directory name
+    let criteria: [&dyn Fn(&Tree) -> bool; 4] = [
+        &|t| t.branch == tree,
+        &|t| t.name == tree,
+        &|t| t.path.file_name().is_some_and(|name| name == tree),
+        &|t| normalize(&t.branch, normalization) == normalized,
+    ];
+
+    for matches in criteria {
+        let found: Vec<&Tree> = root_trees.iter().filter(|t| matches(t)).collect();
+
+        match found.as_slice() {
+            [] => continue,
+            [only] => return Ok((*only).clone()),
+            several => {
+                let candidates: Vec<String> =
+                    several.iter().map(|t| format!("{} (branch '{}')", t.name, t.branch)).collect();
+                anyhow::bail!(
+                    "'{}' matches several trees in root '{}': {}. Use the branch or tree name instead",
+                    tree,
+                    root,
+                    candidates.join(", ")
+                );
+            }
+        }
     }
+
+    anyhow::bail!("Tree '{}' not found in root '{}'", tree, root)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::application::test_application;
+    use crate::application::{init_test_repo, test_application};
     use crate::roots::clone;
     use crate::trees::create;
     use std::collections::HashMap;
@@ -53,7 +78,7 @@ mod tests {
     fn test_tree_does_not_exist_empty_trees() {
         let application = test_application(vec![], vec![], HashMap::new());
 
-        clone::call(&application.roots_dir, TEST_REPO_URL.to_string()).unwrap();
+        clone::call(&application, TEST_REPO_URL.to_string()).unwrap();
 
         let result = call(
             &application,
@@ -74,7 +99,7 @@ mod tests {
     fn test_tree_does_not_exist_nonempty_trees() {
         let application = test_application(vec![], vec![], HashMap::new());
 
-        clone::call(&application.roots_dir, TEST_REPO_URL.to_string()).unwrap();
+        clone::call(&application, TEST_REPO_URL.to_string()).unwrap();
         create::call(&application, "test-repo", "other-tree").unwrap();
 
         let result = call(
@@ -96,7 +121,7 @@ mod tests {
     fn test_tree_exists() {
         let application = test_application(vec![], vec![], HashMap::new());
 
-        clone::call(&application.roots_dir, TEST_REPO_URL.to_string()).unwrap();
+        clone::call(&application, TEST_REPO_URL.to_string()).unwrap();
         create::call(&application, "test-repo", "feature").unwrap();
 
         let result = call(&application, "test-repo", "feature");
@@ -106,4 +131,21 @@ mod tests {
         assert_eq!(tree.branch, "feature");
         assert!(tree.path.ends_with("test-repo--feature"));
     }
+
+    #[test]
+    fn test_tree_with_shared_normalized_name() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        init_test_repo(&application.roots_dir.join("local-repo"));
+        create::call(&application, "local-repo", "feat/a-b").unwrap();
+        create::call(&application, "local-repo", "feat@a-b").unwrap();
+
+        let by_branch = call(&application, "local-repo", "feat@a-b").unwrap();
+        let by_name = call(&application, "local-repo", "local-repo--feat--a-b").unwrap();
+        let err = call(&application, "local-repo", "feat--a-b").unwrap_err();
+
+        assert_eq!(by_branch.branch, "feat@a-b");
+        assert_eq!(by_name.branch, "feat/a-b");
+        assert!(err.to_string().starts_with("'feat--a-b' matches several trees in root 'local-repo'"));
+        assert!(err.to_string().contains("(branch 'feat@a-b')"));
+    }
 }
@@ -1,7 +1,7 @@
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use super::{Tree, Trees, RootsTrees};
+use super::{Tree, Trees, RootsTrees, tree_name_from_path};
 use crate::application::Application;
 use crate::roots;
 use crate::utils::git::Git;
@@ -26,7 +26,7 @@ pub fn call(application: &Application, root: &Option<String>) -> Result<RootsTre
             Err(_) => String::from(""),
         };
         let default_branch = git.default_branch()?;
-        let root_trees = root_trees(git_root_trees, &default_branch)?;
+        let root_trees = root_trees(git_root_trees, &application.trees_dir, &default_branch)?;
         trees.insert(root.name.clone(), root_trees);
     }
 
@@ -34,7 +34,7 @@ pub fn call(application: &Application, root: &Option<String>) -> Result<RootsTre
 }
 
 
-fn root_trees(raw_trees: String, default_branch: &str) -> Result<Trees> {
+fn root_trees(raw_trees: String, trees_dir: &Path, default_branch: &str) -> Result<Trees> {
     if raw_trees.trim().is_empty() {
         return Ok(vec![]);
     }
@@ -54,14 +54,13 @@ fn root_trees(raw_trees: String, default_branch: &str) -> Result<Trees> {
             let branch = lines
                 .get(2)
                 .and_then(|line| line.strip_prefix("branch refs/heads/"))?;
-            let name = path.split("/").last().unwrap_or("undefined");
 
             if branch == default_branch {
                 return None;
             }
 
             Some(Tree {
-                name: name.to_string(),
+                name: tree_name_from_path(trees_dir, Path::new(path)),
                 path: PathBuf::from(path),
                 branch: branch.to_string(),
                 head: head.to_string(),
@@ -207,14 +206,14 @@ mod test {
 
     #[test]
     fn test_root_trees_empty_string() {
-        let result = root_trees(String::from(""), "main").unwrap();
+        let result = root_trees(String::from(""), Path::new("/path/to/trees"), "main").unwrap();
 
         assert!(result.is_empty());
     }
 
     #[test]
     fn test_root_trees_empty_string_with_white_space() {
-        let result = root_trees(String::from("    \n  \t  "), "main").unwrap();
+        let result = root_trees(String::from("    \n  \t  "), Path::new("/path/to/trees"), "main").unwrap();
 
         assert!(result.is_empty());
     }
@@ -232,7 +231,7 @@ mod test {
         )
         .to_string();
 
-        let result = root_trees(input, "main").unwrap();
+        let result = root_trees(input, Path::new("/path/to/trees"), "main").unwrap();
 
         assert_eq!(result.len(), 1);
 
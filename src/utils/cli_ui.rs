@@ -1,7 +1,10 @@
 use console::style;
 
 use crate::trees::Tree;
+use crate::trees::background::SetupStatus;
+use crate::trees::ports::Allocation;
 use crate::roots::Root;
+use crate::trust::TrustedConfig;
 
 pub fn context(msg: &str) -> String {
     format!("{}", style(msg).dim())
@@ -41,7 +44,35 @@ pub fn tree_with_path(root: &str, tree: &Tree) -> String {
     format!("{} {} {}", style(f_root).cyan(), tree.branch, style(path).dim())
 }
 
+pub fn tree_with_status(root: &str, tree: &Tree, status: Option<SetupStatus>) -> String {
+    let line = tree_with_path(root, tree);
+    let Some(status) = status else {
+        return line;
+    };
+
+    let f_status = format!("(setup {})", status.label());
+    let f_status = match status {
+        SetupStatus::Running => style(f_status).yellow(),
+        SetupStatus::Succeeded => style(f_status).green(),
+        SetupStatus::Failed => style(f_status).red(),
+    };
+    format!("{} {}", line, f_status)
+}
+
+pub fn trusted_config(root_path: &str, config: &TrustedConfig) -> String {
+    let prefix: String = config.sha256.chars().take(12).collect();
+    let hash = format!("-> sha256 {}", prefix);
+    format!("{} {}", root_path, style(hash).dim())
+}
+
 pub fn root_with_path(root: &Root) -> String {
     let path = format!("-> {}", root.path.display());
     format!("[{}] {}", root.name, style(path).dim())
 }
+
+pub fn tree_ports(tree_name: &str, allocation: &Allocation) -> String {
+    let f_root = format!("[{}]", allocation.root);
+    let ports: Vec<String> = allocation.ports.iter().map(|p| p.to_string()).collect();
+    let f_ports = format!("-> {}", ports.join(", "));
+    format!("{} {} {}", style(f_root).cyan(), tree_name, style(f_ports).dim())
+}
@@ -1,5 +1,9 @@
 use anyhow::Result;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use thiserror::Error;
 
@@ -16,8 +20,9 @@ pub enum ExecError {
     IoError(#[from] std::io::Error),
 }
 
-// TODO: Background execution
-pub fn call(base_dir: &PathBuf, command: &str) -> Result<(), ExecError> {
+pub type ExecEnv = BTreeMap<String, String>;
+
+pub fn call(base_dir: &PathBuf, command: &str, env: &ExecEnv) -> Result<(), ExecError> {
     let start = format!(">> command: {}", command);
     println!("{}", cli_ui::context(&start));
 
@@ -25,6 +30,7 @@ pub fn call(base_dir: &PathBuf, command: &str) -> Result<(), ExecError> {
         .arg("-c")
         .arg(&command)
         .current_dir(base_dir)
+        .envs(env)
         .stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
@@ -43,6 +49,76 @@ pub fn call(base_dir: &PathBuf, command: &str) -> Result<(), ExecError> {
     Ok(())
 }
 
+/// Like `call`, but without stdin and with every output line prefixed by
+/// `prefix`, so commands running at the same time can be told apart
+pub fn call_prefixed(base_dir: &PathBuf, command: &str, env: &ExecEnv, prefix: &str) -> Result<(), ExecError> {
+    let start = format!("{} >> command: {}", prefix, command);
+    println!("{}", cli_ui::context(&start));
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(base_dir)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdout = child.stdout.take().map(BufReader::new);
+    let stderr = child.stderr.take().map(BufReader::new);
+
+    std::thread::scope(|scope| {
+        if let Some(stderr) = stderr {
+            scope.spawn(|| {
+                for line in stderr.lines().map_while(Result::ok) {
+                    eprintln!("{} {}", cli_ui::context(prefix), line);
+                }
+            });
+        }
+        if let Some(stdout) = stdout {
+            for line in stdout.lines().map_while(Result::ok) {
+                println!("{} {}", cli_ui::context(prefix), line);
+            }
+        }
+    });
+
+    let status = child.wait()?;
+
+    if !status.success() {
+        let end = format!("{} << command error", prefix);
+        eprintln!("{}", cli_ui::critical(&end));
+        return Err(ExecError::CommandFailed {
+            command: command.to_string(),
+            code: status.code().unwrap_or(-1),
+        })
+    }
+
+    let end = format!("{} << command done", prefix);
+    println!("{}", cli_ui::context(&end));
+
+    Ok(())
+}
+
+/// Starts `script` in its own process group, detached from the terminal,
+/// with its output written to `log_file`. Returns the process id
+pub fn spawn_detached(base_dir: &PathBuf, script: &str, env: &ExecEnv, log_file: &Path) -> Result<u32, ExecError> {
+    let log = File::create(log_file)?;
+
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(script)
+        .current_dir(base_dir)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .process_group(0)
+        .spawn()?;
+
+    Ok(child.id())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -54,7 +130,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let base_dir = temp_dir.path().to_path_buf();
 
-        call(&base_dir, "echo testing > testfile.txt").unwrap();
+        call(&base_dir, "echo testing > testfile.txt", &ExecEnv::new()).unwrap();
 
         assert!(base_dir.join("testfile.txt").exists());
         assert_eq!(
@@ -63,12 +139,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_exec_with_env() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_dir = temp_dir.path().to_path_buf();
+        let env = ExecEnv::from([("FOREST_TEST_VAR".to_string(), "from-env".to_string())]);
+
+        call(&base_dir, "echo $FOREST_TEST_VAR > testfile.txt", &env).unwrap();
+
+        assert_eq!(
+            read_to_string(base_dir.join("testfile.txt")).unwrap(),
+            "from-env\n"
+        );
+    }
+
+    #[test]
+    fn test_exec_prefixed() {
+        let temp_dir = TempDir::new().unwrap();
+        let base_dir = temp_dir.path().to_path_buf();
+
+        call_prefixed(&base_dir, "echo out; echo err >&2; echo done > testfile.txt", &ExecEnv::new(), "[step]").unwrap();
+        let result = call_prefixed(&base_dir, "exit 3", &ExecEnv::new(), "[step]");
+
+        assert!(base_dir.join("testfile.txt").exists());
+        assert!(matches!(result, Err(ExecError::CommandFailed { code: 3, .. })));
+    }
+
     #[test]
     fn test_exec_with_invalid_command() {
         let temp_dir = TempDir::new().unwrap();
         let base_dir = temp_dir.path().to_path_buf();
 
-        let result = call(&base_dir, "nosuchcommand");
+        let result = call(&base_dir, "nosuchcommand", &ExecEnv::new());
 
         assert!(result.is_err());
         assert!(
@@ -41,6 +41,13 @@ impl Git {
         self.parsed_output("clone", output)
     }
 
+    /// Whether git accepts `name` as a branch name (`git check-ref-format --branch`)
+    pub fn is_valid_branch_name(name: &str) -> Result<bool, GitError> {
+        let output = Command::new("git").args(["check-ref-format", "--branch"]).arg(name).output()?;
+
+        Ok(output.status.success())
+    }
+
     pub fn latest_default(&self) -> Result<GitSuccess, GitError> {
         if self.is_local_only()? {
             return Ok(GitSuccess {
@@ -105,6 +112,19 @@ impl Git {
         self.parsed_output("branch-delete", output)
     }
 
+    /// Ignored, untracked files relative to the repository root. Wholly
+    /// ignored directories are listed once, with a trailing `/`
+    pub fn list_ignored(&self) -> Result<Vec<String>, GitError> {
+        let output = self
+            .based_git()
+            .args(["ls-files", "-z", "--others", "--ignored", "--exclude-standard", "--directory"])
+            .output()?;
+        let p_output = self.parsed_output("ls-files", output)?;
+
+        // NUL-separated, so paths come unquoted whatever core.quotePath says
+        Ok(p_output.stdout.split('\0').filter(|path| !path.is_empty()).map(String::from).collect())
+    }
+
     fn is_local_only(&self) -> Result<bool, GitError> {
         let output = self.based_git().arg("remote").output()?;
 
@@ -350,6 +370,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_list_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo_path = setup_git_repo_with_branch(&temp_dir, "main");
+        fs::write(repo_path.join(".gitignore"), ".env*\nnode_modules/\n").unwrap();
+        fs::write(repo_path.join(".env"), "SECRET=1").unwrap();
+        fs::write(repo_path.join(".env café"), "SECRET=2").unwrap();
+        fs::create_dir_all(repo_path.join("node_modules").join("left-pad")).unwrap();
+        fs::write(repo_path.join("node_modules").join("left-pad").join("index.js"), "").unwrap();
+        fs::write(repo_path.join("untracked.txt"), "not ignored").unwrap();
+
+        let git = Git::new(&repo_path);
+        let result = git.list_ignored().unwrap();
+
+        assert_eq!(result, vec![".env".to_string(), ".env café".to_string(), "node_modules/".to_string()]);
+    }
+
     #[test]
     fn test_based_git_returns_command() {
         let temp_dir = TempDir::new().unwrap();
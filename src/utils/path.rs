@@ -1,7 +1,17 @@
-use std::path::PathBuf;
+use regex::{Captures, Regex};
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
 
 const PROJECT_NAME: &str = "forest";
 
+#[derive(Error, Debug)]
+pub enum PathError {
+    #[error("Environment variable '{0}' is not defined")]
+    UndefinedVariable(String),
+    #[error("Could not determine home directory")]
+    NoHomeDir,
+}
+
 pub fn home_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
     let base_dirs = directories::BaseDirs::new().ok_or("Could not determine home directory")?;
     let home = base_dirs.home_dir();
@@ -9,6 +19,11 @@ pub fn home_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
     Ok(home.to_path_buf())
 }
 
+/// Configuration file given with `--config` (`flag`) or, failing that, `$FOREST_CONFIG`
+pub fn config_file_override(flag: Option<PathBuf>) -> Option<PathBuf> {
+    flag.or_else(|| std::env::var_os("FOREST_CONFIG").filter(|value| !value.is_empty()).map(PathBuf::from))
+}
+
 pub fn config_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
     let dirs = directories::ProjectDirs::from("", "", PROJECT_NAME)
         .ok_or("Could not find config directory")?;
@@ -18,3 +33,120 @@ pub fn config_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
 
     Ok(config_dir.to_path_buf())
 }
+
+/// Turns a path setting into an absolute path: expands a leading `~` and
+/// `$VAR`/`${VAR}`, resolves relative paths against `relative_to` and
+/// drops `.` and `..` components
+pub fn expand(value: &str, relative_to: &Path) -> Result<PathBuf, PathError> {
+    expand_with(value, relative_to, |name| std::env::var(name).ok())
+}
+
+fn expand_with(value: &str, relative_to: &Path, lookup: impl Fn(&str) -> Option<String>) -> Result<PathBuf, PathError> {
+    let regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}|\$([A-Za-z_][A-Za-z0-9_]*)").unwrap();
+    let mut undefined: Option<String> = None;
+
+    let expanded = regex.replace_all(value, |captures: &Captures| {
+        let name = captures.get(1).or(captures.get(2)).unwrap().as_str();
+        lookup(name).unwrap_or_else(|| {
+            undefined.get_or_insert(name.to_string());
+            String::new()
+        })
+    });
+
+    if let Some(name) = undefined {
+        return Err(PathError::UndefinedVariable(name));
+    }
+
+    let path = match expanded.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let home = home_dir().map_err(|_| PathError::NoHomeDir)?;
+            home.join(rest.trim_start_matches('/'))
+        },
+        _ => PathBuf::from(expanded.as_ref()),
+    };
+
+    Ok(normalize(&relative_to.join(path)))
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            other => normalized.push(other),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/forest".to_string()),
+            "PROJECTS" => Some("/srv/projects".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_absolute() {
+        let path = expand_with("/srv/forest/./roots/..", Path::new("/config"), lookup).unwrap();
+
+        assert_eq!(path, PathBuf::from("/srv/forest"));
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let path = expand_with("~/Projects", Path::new("/config"), lookup).unwrap();
+
+        assert_eq!(path, home_dir().unwrap().join("Projects"));
+    }
+
+    #[test]
+    fn test_expand_variables() {
+        assert_eq!(
+            expand_with("$HOME/Projects", Path::new("/config"), lookup).unwrap(),
+            PathBuf::from("/home/forest/Projects")
+        );
+        assert_eq!(
+            expand_with("${PROJECTS}/forest", Path::new("/config"), lookup).unwrap(),
+            PathBuf::from("/srv/projects/forest")
+        );
+    }
+
+    #[test]
+    fn test_expand_relative() {
+        let path = expand_with("../Projects", Path::new("/home/forest/.config/forest"), lookup).unwrap();
+
+        assert_eq!(path, PathBuf::from("/home/forest/.config/Projects"));
+    }
+
+    #[test]
+    fn test_expand_undefined_variable() {
+        let err = expand_with("$NOPE/Projects", Path::new("/config"), lookup).unwrap_err();
+
+        assert_eq!(err.to_string(), "Environment variable 'NOPE' is not defined");
+    }
+
+    #[test]
+    fn test_expand_keeps_other_tildes() {
+        let path = expand_with("/srv/~backup", Path::new("/config"), lookup).unwrap();
+
+        assert_eq!(path, PathBuf::from("/srv/~backup"));
+    }
+
+    #[test]
+    fn test_config_file_override_prefers_flag() {
+        let file = config_file_override(Some(PathBuf::from("/tmp/ci.toml")));
+
+        assert_eq!(file, Some(PathBuf::from("/tmp/ci.toml")));
+    }
+}
@@ -0,0 +1,63 @@
+use regex::{Captures, Regex};
+use std::collections::BTreeMap;
+
+pub type TemplateVars = BTreeMap<String, String>;
+
+/// Replaces `{{name}}` placeholders with their value in `vars`.
+/// Returns the rendered content and the placeholders left untouched
+/// because no value was available for them
+pub fn render(content: &str, vars: &TemplateVars) -> (String, Vec<String>) {
+    let regex = Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").unwrap();
+    let mut unknown: Vec<String> = vec![];
+
+    let rendered = regex.replace_all(content, |captures: &Captures| {
+        let name = &captures[1];
+        match vars.get(name) {
+            Some(value) => value.clone(),
+            None => {
+                if !unknown.iter().any(|u| u == name) {
+                    unknown.push(name.to_string());
+                }
+                captures[0].to_string()
+            }
+        }
+    });
+
+    (rendered.to_string(), unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> TemplateVars {
+        TemplateVars::from([
+            ("root".to_string(), "api".to_string()),
+            ("branch".to_string(), "feat/login".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_render_known_vars() {
+        let (rendered, unknown) = render("COMPOSE_PROJECT_NAME={{root}}-{{ branch }}", &vars());
+
+        assert_eq!(rendered, "COMPOSE_PROJECT_NAME=api-feat/login");
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn test_render_unknown_vars() {
+        let (rendered, unknown) = render("{{root}} {{missing}} {{missing}}", &vars());
+
+        assert_eq!(rendered, "api {{missing}} {{missing}}");
+        assert_eq!(unknown, vec!["missing".to_string()]);
+    }
+
+    #[test]
+    fn test_render_without_placeholders() {
+        let (rendered, unknown) = render("plain { text }", &vars());
+
+        assert_eq!(rendered, "plain { text }");
+        assert!(unknown.is_empty());
+    }
+}